@@ -0,0 +1,32 @@
+CREATE VIRTUAL TABLE IF NOT EXISTS workspace_fts USING fts5(
+    description,
+    long_description,
+    content='workspace',
+    content_rowid='id'
+);
+
+-- keep the external content index in step with workspace
+CREATE TRIGGER workspace_fts_ai AFTER INSERT ON workspace BEGIN
+    INSERT INTO workspace_fts ( rowid, description, long_description )
+    VALUES ( new.id, new.description, new.long_description );
+END;
+CREATE TRIGGER workspace_fts_ad AFTER DELETE ON workspace BEGIN
+    INSERT INTO workspace_fts ( workspace_fts, rowid, description, long_description )
+    VALUES ( 'delete', old.id, old.description, old.long_description );
+END;
+CREATE TRIGGER workspace_fts_au AFTER UPDATE ON workspace BEGIN
+    INSERT INTO workspace_fts ( workspace_fts, rowid, description, long_description )
+    VALUES ( 'delete', old.id, old.description, old.long_description );
+    INSERT INTO workspace_fts ( rowid, description, long_description )
+    VALUES ( new.id, new.description, new.long_description );
+END;
+
+INSERT INTO workspace_fts ( workspace_fts ) VALUES ( 'rebuild' );
+
+-- text blobs at the default branch of each workspace
+CREATE VIRTUAL TABLE IF NOT EXISTS workspace_blob_fts USING fts5(
+    workspace_id UNINDEXED,
+    commit_id UNINDEXED,
+    path,
+    content
+);
@@ -0,0 +1,12 @@
+CREATE TABLE IF NOT EXISTS workspace_metadata (
+    id INTEGER PRIMARY KEY NOT NULL,
+    workspace_id INTEGER NOT NULL,
+    commit_id TEXT NOT NULL,
+    path TEXT NOT NULL,  -- the file the triple was extracted from
+    subject TEXT NOT NULL,
+    predicate TEXT NOT NULL,
+    object TEXT NOT NULL,
+    FOREIGN KEY(workspace_id) REFERENCES workspace(id)
+);
+CREATE INDEX workspace_metadata_idx_workspace_id_commit_id ON workspace_metadata(workspace_id, commit_id);
+CREATE INDEX workspace_metadata_idx_predicate ON workspace_metadata(predicate);
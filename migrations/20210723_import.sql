@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS workspace_import (
+    id INTEGER PRIMARY KEY NOT NULL,
+    workspace_id INTEGER NOT NULL,
+    commit_id TEXT NOT NULL,
+    path TEXT NOT NULL,  -- the importing model
+    href TEXT NOT NULL,  -- as written in the model
+    target_url TEXT,  -- set if outside of this workspace
+    target_workspace_id INTEGER,  -- null if not registered
+    target_commit_id TEXT,
+    target_path TEXT,
+    FOREIGN KEY(workspace_id) REFERENCES workspace(id),
+    FOREIGN KEY(target_workspace_id) REFERENCES workspace(id)
+);
+CREATE INDEX workspace_import_idx_workspace_id_commit_id ON workspace_import(workspace_id, commit_id);
+CREATE INDEX workspace_import_idx_target_workspace_id ON workspace_import(target_workspace_id);
@@ -0,0 +1,25 @@
+CREATE TABLE IF NOT EXISTS user (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    created INTEGER NOT NULL
+);
+CREATE UNIQUE INDEX user_idx_name ON user(name);
+
+-- ordered from most to least privileged
+CREATE TABLE IF NOT EXISTS role (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL
+);
+INSERT INTO role ( id, name ) VALUES ( 0, 'owner' ), ( 1, 'editor' ), ( 2, 'viewer' );
+
+CREATE TABLE IF NOT EXISTS workspace_acl (
+    id INTEGER PRIMARY KEY NOT NULL,
+    workspace_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    role_id INTEGER NOT NULL,
+    FOREIGN KEY(workspace_id) REFERENCES workspace(id),
+    FOREIGN KEY(user_id) REFERENCES user(id),
+    FOREIGN KEY(role_id) REFERENCES role(id)
+);
+CREATE UNIQUE INDEX workspace_acl_idx_workspace_id_user_id ON workspace_acl(workspace_id, user_id);
+CREATE INDEX workspace_acl_idx_user_id ON workspace_acl(user_id);
@@ -0,0 +1,3 @@
+-- 0 = public, 1 = private, 2 = embargoed until embargo_until
+ALTER TABLE workspace ADD COLUMN visibility INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE workspace ADD COLUMN embargo_until INTEGER;
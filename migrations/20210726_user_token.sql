@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS user_token (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    token_hash TEXT NOT NULL,  -- sha256 of the token; the token itself is never stored
+    description TEXT NOT NULL,
+    scope INTEGER NOT NULL,
+    created INTEGER NOT NULL,
+    expires INTEGER,
+    revoked INTEGER,
+    FOREIGN KEY(user_id) REFERENCES user(id)
+);
+CREATE UNIQUE INDEX user_token_idx_token_hash ON user_token(token_hash);
+CREATE INDEX user_token_idx_user_id ON user_token(user_id);
@@ -0,0 +1,20 @@
+-- no foreign key on workspace_id, events outlive what they refer to
+CREATE TABLE IF NOT EXISTS audit_event (
+    id INTEGER PRIMARY KEY NOT NULL,
+    created INTEGER NOT NULL,
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    workspace_id INTEGER,
+    target TEXT NOT NULL,
+    before TEXT,  -- json
+    after TEXT  -- json
+);
+CREATE INDEX audit_event_idx_workspace_id ON audit_event(workspace_id);
+CREATE INDEX audit_event_idx_created ON audit_event(created);
+
+CREATE TRIGGER audit_event_no_update BEFORE UPDATE ON audit_event BEGIN
+    SELECT RAISE(ABORT, 'audit_event is append-only');
+END;
+CREATE TRIGGER audit_event_no_delete BEFORE DELETE ON audit_event BEGIN
+    SELECT RAISE(ABORT, 'audit_event is append-only');
+END;
@@ -0,0 +1,3 @@
+-- set when the workspace was archived; archived workspaces are kept but
+-- no longer listed, searched or synced
+ALTER TABLE workspace ADD COLUMN archived INTEGER;
@@ -0,0 +1,10 @@
+CREATE TABLE IF NOT EXISTS workspace_maintenance (
+    id INTEGER PRIMARY KEY NOT NULL,
+    workspace_id INTEGER NOT NULL,
+    start INTEGER NOT NULL,
+    end INTEGER NOT NULL,
+    size_before INTEGER NOT NULL,  -- bytes on disk
+    size_after INTEGER NOT NULL,
+    FOREIGN KEY(workspace_id) REFERENCES workspace(id)
+);
+CREATE INDEX workspace_maintenance_idx_workspace_id ON workspace_maintenance(workspace_id);
@@ -0,0 +1,4 @@
+-- the normalized form of url, see model::workspace_url::url_key; left
+-- null for workspaces registered before, until fsck --fix fills it in
+ALTER TABLE workspace ADD COLUMN url_key TEXT;
+CREATE UNIQUE INDEX workspace_idx_url_key ON workspace(url_key);
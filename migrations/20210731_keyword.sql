@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS keyword (
+    id INTEGER PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL  -- normalized, see normalize_keyword
+);
+CREATE UNIQUE INDEX keyword_idx_name ON keyword(name);
+
+CREATE TABLE IF NOT EXISTS workspace_keyword (
+    id INTEGER PRIMARY KEY NOT NULL,
+    workspace_id INTEGER NOT NULL,
+    keyword_id INTEGER NOT NULL,
+    source INTEGER NOT NULL,  -- 0 assigned, 1 extracted from the metadata
+    FOREIGN KEY(workspace_id) REFERENCES workspace(id),
+    FOREIGN KEY(keyword_id) REFERENCES keyword(id)
+);
+CREATE UNIQUE INDEX workspace_keyword_idx_workspace_id_keyword_id ON workspace_keyword(workspace_id, keyword_id);
+CREATE INDEX workspace_keyword_idx_keyword_id ON workspace_keyword(keyword_id);
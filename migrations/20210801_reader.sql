@@ -0,0 +1,10 @@
+-- Who may read each workspace from when, regardless of archiving: a NULL
+-- user_id is anyone, i.e. the workspace is public, or will be once the
+-- embargo is over; otherwise it is a user with any role on the workspace.
+CREATE VIEW IF NOT EXISTS workspace_reader AS
+SELECT id AS workspace_id, NULL AS user_id, CASE visibility WHEN 0 THEN 0 ELSE embargo_until END AS since
+FROM workspace
+WHERE visibility = 0 OR (visibility = 2 AND embargo_until IS NOT NULL)
+UNION ALL
+SELECT workspace_id, user_id, 0 AS since
+FROM workspace_acl;
@@ -0,0 +1,3 @@
+-- 0 when the object is an IRI or blank node; what was extracted before
+-- this was kept is taken as literal until extracted again
+ALTER TABLE workspace_metadata ADD COLUMN literal INTEGER NOT NULL DEFAULT 1;
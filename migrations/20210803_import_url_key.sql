@@ -0,0 +1,5 @@
+-- the key of target_url, see model::workspace_url::url_key, so imports can
+-- be linked up to a workspace registered after them; left null for imports
+-- indexed before, until they are indexed again
+ALTER TABLE workspace_import ADD COLUMN target_url_key TEXT;
+CREATE INDEX workspace_import_idx_target_url_key ON workspace_import(target_url_key);
@@ -1,27 +1,81 @@
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
 use git2::Object;
 use sqlx::sqlite::SqlitePool;
+use std::collections::HashSet;
 use std::env;
 use std::io::{self, Write};
 use std::path::PathBuf;
 use std::process;
 use structopt::StructOpt;
 
+use pmrmodel::error::PmrError;
 use pmrmodel::model::backend::{
     SqliteBackend
 };
-use pmrmodel::model::workspace::WorkspaceBackend;
+use pmrmodel::model::audit::AuditBackend;
+use pmrmodel::model::search::SearchBackend;
+use pmrmodel::model::user::{
+    Caller,
+    UserBackend,
+};
+use pmrmodel::model::user_token::{
+    TokenScope,
+    UserTokenBackend,
+};
+use pmrmodel::model::workspace::{
+    WorkspaceBackend,
+    WorkspacePage,
+    WorkspaceQuery,
+    WorkspaceSort,
+    WorkspaceVisibility,
+};
+use pmrmodel::model::workspace_acl::{
+    WorkspaceAclBackend,
+    WorkspaceRole,
+};
+use pmrmodel::model::workspace_import::WorkspaceImportBackend;
+use pmrmodel::model::workspace_keyword::WorkspaceKeywordBackend;
+use pmrmodel::model::workspace_maintenance::WorkspaceMaintenanceBackend;
+use pmrmodel::model::workspace_metadata::WorkspaceMetadataBackend;
 use pmrmodel::model::workspace_sync::WorkspaceSyncBackend;
 use pmrmodel::model::workspace_tag::WorkspaceTagBackend;
+use pmrmodel::model::workspace_transfer::WorkspaceTransferBackend;
+use pmrmodel::model::workspace_url::check_workspace_url;
+use pmrmodel::repo::backup::{
+    backup,
+    database_path,
+    restore,
+};
+use pmrmodel::repo::fsck::fsck;
 use pmrmodel::repo::git::{
+    ByteRange,
     GitPmrAccessor,
+    GrepQuery,
 
+    blame,
+    delete_workspace_and_repo,
+    file_history,
+    grep,
+    git_maintain_workspace,
     git_sync_workspace,
     index_tags,
+    index_search_blobs,
+    index_metadata,
+    index_imports,
+    index_keywords,
+    list_tree,
+    probe_url,
+    resolve_commit_id,
     get_obj_by_spec,
     process_pathinfo,
 
+    stat_blob,
+    stream_blob_range,
     stream_git_result_set,
-    stream_git_result_set_blob,
+};
+use pmrmodel::repo::register::{
+    bulk_register,
+    read_manifest,
 };
 
 #[derive(StructOpt)]
@@ -31,6 +85,14 @@ struct Args {
 
     #[structopt(short = "v", long = "verbose", parse(from_occurrences))]
     verbose: usize,
+
+    // act as this user rather than as the system
+    #[structopt(short = "u", long = "user")]
+    user: Option<String>,
+
+    // act as the user the API token belongs to
+    #[structopt(long = "token", env = "PMR_TOKEN", hide_env_values = true)]
+    token: Option<String>,
 }
 
 #[derive(StructOpt)]
@@ -40,6 +102,17 @@ enum Command {
         description: String,
         #[structopt(short = "l", long = "longdesc", default_value = "")]
         long_description: String,
+        // register without checking that the url can be reached
+        #[structopt(long)]
+        no_probe: bool,
+    },
+    // register every workspace listed in a csv, json or yaml manifest
+    BulkRegister {
+        manifest: PathBuf,
+        #[structopt(short, long)]
+        sync: bool,
+        #[structopt(long)]
+        no_probe: bool,
     },
     Update {
         workspace_id: i64,
@@ -47,11 +120,58 @@ enum Command {
         #[structopt(short = "l", long = "longdesc", default_value = "")]
         long_description: String,
     },
+    Visibility {
+        workspace_id: i64,
+        visibility: WorkspaceVisibility,
+        // end of the embargo, as a date or RFC 3339 timestamp
+        #[structopt(long, parse(try_from_str = parse_timestamp))]
+        until: Option<i64>,
+    },
+    Archive {
+        workspace_id: i64,
+        #[structopt(short, long)]
+        undo: bool,
+    },
+    Delete {
+        workspace_id: i64,
+        // report what would be removed without removing anything
+        #[structopt(short = "n", long)]
+        dry_run: bool,
+        // keep the repo under the quarantine directory instead of removing it
+        #[structopt(short, long)]
+        quarantine: bool,
+    },
+    Backup {
+        dest: PathBuf,
+    },
+    Restore {
+        src: PathBuf,
+    },
+    Export {
+        #[structopt(short, long)]
+        workspace_id: Vec<i64>,
+        // write to this file rather than to stdout
+        #[structopt(short, long)]
+        output: Option<PathBuf>,
+    },
+    Import {
+        input: PathBuf,
+    },
+    Fsck {
+        // attempt to fix the issues that are safe to fix
+        #[structopt(long)]
+        fix: bool,
+    },
     Sync {
         workspace_id: i64,
         #[structopt(short, long)]
         log: bool,
     },
+    Maintain {
+        workspace_id: i64,
+        #[structopt(short, long)]
+        log: bool,
+    },
     Tags {
         workspace_id: i64,
         #[structopt(short, long)]
@@ -70,9 +190,254 @@ enum Command {
         path: Option<String>,
         #[structopt(short, long)]
         raw: bool,
+        // with raw, only write these bytes, as in an HTTP Range header
+        #[structopt(long)]
+        range: Option<String>,
+    },
+    // every path below the directory, with the commit that last changed it
+    Tree {
+        workspace_id: i64,
+        #[structopt(short, long)]
+        commit_id: Option<String>,
+        #[structopt(short, long)]
+        path: Option<String>,
+    },
+    // the commits that changed the path, following renames
+    History {
+        workspace_id: i64,
+        path: Option<String>,
+        #[structopt(short, long)]
+        commit_id: Option<String>,
+    },
+    Blame {
+        workspace_id: i64,
+        path: String,
+        #[structopt(short, long)]
+        commit_id: Option<String>,
+    },
+    // search the text files at the commit for lines matching the pattern
+    Grep {
+        workspace_id: i64,
+        pattern: String,
+        #[structopt(short, long)]
+        commit_id: Option<String>,
+        #[structopt(short, long)]
+        path: Option<String>,
+        // take the pattern literally rather than as a regex
+        #[structopt(short = "F", long)]
+        literal: bool,
+        #[structopt(short, long)]
+        ignore_case: bool,
+        // only search files of this kind, e.g. cellml, sedml or text
+        #[structopt(short, long)]
+        kind: Option<String>,
+        #[structopt(short = "C", long, default_value = "0")]
+        context: usize,
+        #[structopt(short = "n", long, default_value = "1000")]
+        max_matches: usize,
+    },
+    Metadata {
+        workspace_id: i64,
+        #[structopt(short, long)]
+        commit_id: Option<String>,
+        #[structopt(short, long)]
+        index: bool,
+    },
+    Imports {
+        workspace_id: i64,
+        #[structopt(short, long)]
+        commit_id: Option<String>,
+        #[structopt(short, long)]
+        path: Option<String>,
+        #[structopt(short, long)]
+        index: bool,
+        #[structopt(short, long)]
+        reverse: bool,
+    },
+    User {
+        #[structopt(subcommand)]
+        cmd: Option<UserCommand>,
+    },
+    Acl {
+        workspace_id: i64,
+        #[structopt(subcommand)]
+        cmd: Option<AclCommand>,
+    },
+    Keyword {
+        workspace_id: i64,
+        #[structopt(subcommand)]
+        cmd: Option<KeywordCommand>,
+    },
+    // every keyword in use, with the number of workspaces having it
+    Keywords,
+    Audit {
+        #[structopt(short, long)]
+        workspace_id: Option<i64>,
+        #[structopt(long, parse(try_from_str = parse_timestamp))]
+        since: Option<i64>,
+        // recorded before; a date includes the whole of that day
+        #[structopt(long, parse(try_from_str = parse_until))]
+        until: Option<i64>,
+    },
+    Token {
+        #[structopt(subcommand)]
+        cmd: TokenCommand,
+    },
+    Search {
+        query: String,
+        #[structopt(short, long)]
+        content: bool,
+        #[structopt(short = "n", long, default_value = "20")]
+        limit: i64,
+    },
+    SearchIndex {
+        workspace_id: i64,
+    },
+    List {
+        // match anywhere in the url or description
+        #[structopt(short, long)]
+        filter: Option<String>,
+        #[structopt(short, long)]
+        keyword: Option<String>,
+        // created on or after
+        #[structopt(long, parse(try_from_str = parse_timestamp))]
+        since: Option<i64>,
+        // created before; a date includes the whole of that day
+        #[structopt(long, parse(try_from_str = parse_until))]
+        until: Option<i64>,
+        // one of id, created or url
+        #[structopt(short, long, default_value = "id")]
+        sort: WorkspaceSort,
+        #[structopt(short, long)]
+        desc: bool,
+        #[structopt(short = "n", long, default_value = "20", parse(try_from_str = parse_count))]
+        limit: i64,
+        #[structopt(short, long, default_value = "0", parse(try_from_str = parse_count))]
+        offset: i64,
+    },
+}
+
+#[derive(StructOpt)]
+enum UserCommand {
+    Add {
+        name: String,
     },
 }
 
+#[derive(StructOpt)]
+enum AclCommand {
+    Grant {
+        user: String,
+        role: WorkspaceRole,
+    },
+    Revoke {
+        user: String,
+    },
+}
+
+#[derive(StructOpt)]
+enum KeywordCommand {
+    Add {
+        keyword: String,
+    },
+    Remove {
+        keyword: String,
+    },
+    // fill in keywords from the subjects given in the extracted metadata
+    Extract {
+        #[structopt(short, long)]
+        commit_id: Option<String>,
+    },
+}
+
+#[derive(StructOpt)]
+enum TokenCommand {
+    Create {
+        user: String,
+        #[structopt(short, long, default_value = "read")]
+        scope: TokenScope,
+        #[structopt(short, long, parse(try_from_str = parse_timestamp))]
+        expires: Option<i64>,
+        #[structopt(short, long, default_value = "")]
+        description: String,
+    },
+    List {
+        user: String,
+    },
+    Revoke {
+        token_id: i64,
+    },
+}
+
+impl Command {
+    // The token scope required to run the command. This must be no less than
+    // what the backend asks of the token for it, e.g. the audit log is only
+    // for owners and so needs a write token like anything else owners do.
+    fn scope(&self) -> TokenScope {
+        match self {
+            Command::Sync { log: true, .. }
+            | Command::Maintain { log: true, .. }
+            | Command::Tags { index: false, .. }
+            | Command::Blob { .. }
+            | Command::Info { .. }
+            | Command::Tree { .. }
+            | Command::History { .. }
+            | Command::Blame { .. }
+            | Command::Grep { .. }
+            | Command::Metadata { index: false, .. }
+            | Command::Imports { index: false, .. }
+            | Command::User { cmd: None }
+            | Command::Acl { cmd: None, .. }
+            | Command::Keyword { cmd: None, .. }
+            | Command::Keywords
+            | Command::Export { .. }
+            | Command::Search { .. }
+            | Command::List { .. } => TokenScope::Read,
+            _ => TokenScope::Write,
+        }
+    }
+}
+
+fn parse_timestamp(s: &str) -> anyhow::Result<i64> {
+    match NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        Ok(date) => Ok(Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0).unwrap()).timestamp()),
+        Err(_) => Ok(DateTime::parse_from_rfc3339(s)?.with_timezone(&Utc).timestamp()),
+    }
+}
+
+// The exclusive end of a range, so a date is taken as the midnight that
+// follows it.
+fn parse_until(s: &str) -> anyhow::Result<i64> {
+    match NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        Ok(date) => Ok(Utc.from_utc_datetime(&date.succ_opt()
+            .ok_or_else(|| PmrError::InvalidInput(format!("{} is out of range", s)))?
+            .and_hms_opt(0, 0, 0).unwrap()).timestamp()),
+        Err(_) => parse_timestamp(s),
+    }
+}
+
+fn parse_count(s: &str) -> anyhow::Result<i64> {
+    match s.parse()? {
+        n if n < 0 => Err(PmrError::InvalidInput(format!("{} is negative", n)).into()),
+        n => Ok(n),
+    }
+}
+
+fn print_workspace_page(page: &WorkspacePage, offset: i64) {
+    println!("id - url - description");
+    for rec in page.workspaces.iter() {
+        println!("{}", rec);
+    }
+    if page.workspaces.is_empty() {
+        println!("Showing none of {} workspaces", page.total);
+    }
+    else {
+        println!(
+            "Showing {} to {} of {} workspaces",
+            offset + 1, offset + page.workspaces.len() as i64, page.total);
+    }
+}
+
 fn fetch_envvar(key: &str) -> anyhow::Result<String> {
     match env::var(&key) {
         Err(e) => {
@@ -83,14 +448,39 @@ fn fetch_envvar(key: &str) -> anyhow::Result<String> {
     }
 }
 
+async fn print_import_tree(
+    backend: &SqliteBackend, caller: &Caller, workspace_id: i64, commit_id: &str, path: Option<&str>
+) -> anyhow::Result<()> {
+    let mut stack: Vec<_> = WorkspaceImportBackend::get_workspace_imports(
+        backend, caller, workspace_id, commit_id, path).await?
+        .into_iter().rev().map(|rec| (0, rec)).collect();
+    let mut seen = HashSet::new();
+    while let Some((depth, rec)) = stack.pop() {
+        println!("{}{}", "  ".repeat(depth), rec);
+        if let (Some(target_workspace_id), Some(target_commit_id), Some(target_path)) =
+            (rec.target_workspace_id, &rec.target_commit_id, &rec.target_path)
+        {
+            // imports may be circular
+            if !seen.insert((target_workspace_id, target_commit_id.clone(), target_path.clone())) {
+                continue;
+            }
+            // the import is listed but not followed into a workspace the
+            // caller may not see
+            let children = match WorkspaceImportBackend::get_workspace_imports(
+                backend, caller, target_workspace_id, target_commit_id, Some(target_path)).await
+            {
+                Err(PmrError::NotFound(_)) => continue,
+                result => result?,
+            };
+            stack.extend(children.into_iter().rev().map(|rec| (depth + 1, rec)));
+        }
+    }
+    Ok(())
+}
+
 #[async_std::main]
 #[paw::main]
 async fn main(args: Args) -> anyhow::Result<()> {
-    // TODO make this be sourced from a configuration file of sort...
-    let git_root = PathBuf::from(fetch_envvar("PMR_GIT_ROOT")?);
-    let pool = SqlitePool::connect(&fetch_envvar("DATABASE_URL")?).await?;
-    let backend = SqliteBackend::new(pool);
-
     stderrlog::new()
         .module(module_path!())
         .verbosity(args.verbose + 1)
@@ -98,25 +488,133 @@ async fn main(args: Args) -> anyhow::Result<()> {
         .init()
         .unwrap();
 
+    // TODO make this be sourced from a configuration file of sort...
+    let git_root = PathBuf::from(fetch_envvar("PMR_GIT_ROOT")?);
+    let database_url = fetch_envvar("DATABASE_URL")?;
+
+    // a restore is done before there is any database to connect to
+    if let Some(Command::Restore { src }) = &args.cmd {
+        if args.user.is_some() || args.token.is_some() {
+            anyhow::bail!("a restore can only be done as the system");
+        }
+        let manifest = restore(src, &database_path(&database_url)?, &git_root).await?;
+        println!("Restored database and {} repos from backup made at {}",
+            manifest.repos.len(), Utc.timestamp_opt(manifest.created, 0).unwrap().to_rfc3339());
+        return Ok(());
+    }
+
+    let pool = SqlitePool::connect(&database_url).await?;
+    let backend = SqliteBackend::new(pool);
+    let caller = match (&args.token, &args.user) {
+        (Some(token), _) => {
+            let scope = args.cmd.as_ref().map(Command::scope).unwrap_or(TokenScope::Read);
+            UserTokenBackend::authenticate_token(&backend, token, scope).await?
+        }
+        (None, Some(name)) => Caller::User(UserBackend::get_user_by_name(&backend, name).await?.id),
+        (None, None) => Caller::System,
+    };
+
     match args.cmd {
-        Some(Command::Register { url, description, long_description }) => {
+        Some(Command::Register { url, description, long_description, no_probe }) => {
             println!("Registering workspace with url '{}'...", &url);
-            let workspace_id = WorkspaceBackend::add_workspace(&backend, &url, &description, &long_description).await?;
+            // only urls the caller may register are probed, as the probe
+            // itself would open a local path
+            check_workspace_url(&caller, &url)?;
+            if !no_probe {
+                probe_url(&url).await?;
+            }
+            let workspace_id = WorkspaceBackend::add_workspace(&backend, &caller, &url, &description, &long_description).await?;
             println!("Registered workspace with id {}", workspace_id);
         }
+        Some(Command::BulkRegister { manifest, sync, no_probe }) => {
+            let entries = read_manifest(&manifest)?;
+            println!("Registering {} workspaces from {:?}...", entries.len(), &manifest);
+            let reports = bulk_register(&backend, &caller, &git_root, &entries, !no_probe, sync).await?;
+            println!("url - outcome - id");
+            for report in reports {
+                println!("{}", report);
+            }
+        }
         Some(Command::Update { workspace_id, description, long_description }) => {
             println!("Updating workspace with id {}...", workspace_id);
-            if WorkspaceBackend::update_workspace(&backend, workspace_id, &description, &long_description).await? {
+            if WorkspaceBackend::update_workspace(&backend, &caller, workspace_id, &description, &long_description).await? {
                 println!("Updated workspace id {}", workspace_id);
             }
             else {
                 println!("Invalid workspace id {}", workspace_id);
             }
         }
+        Some(Command::Visibility { workspace_id, visibility, until }) => {
+            if WorkspaceBackend::set_workspace_visibility(&backend, &caller, workspace_id, visibility, until).await? {
+                println!("Set workspace id {} to {:?}", workspace_id, visibility);
+            }
+            else {
+                println!("Invalid workspace id {}", workspace_id);
+            }
+        }
+        Some(Command::Archive { workspace_id, undo }) => {
+            if WorkspaceBackend::set_workspace_archived(&backend, &caller, workspace_id, !undo).await? {
+                println!("{} workspace id {}", if undo { "Unarchived" } else { "Archived" }, workspace_id);
+            }
+            else {
+                println!("Invalid workspace id {}", workspace_id);
+            }
+        }
+        Some(Command::Delete { workspace_id, dry_run, quarantine }) => {
+            let verb = if dry_run { "Would remove" } else { "Removed" };
+            match delete_workspace_and_repo(
+                &backend, &caller, &git_root, workspace_id, quarantine, dry_run).await?
+            {
+                Some((summary, repo_dir)) => {
+                    println!("{} workspace id {}: {}", verb, workspace_id, summary);
+                    match repo_dir {
+                        Some(repo_dir) => println!(
+                            "{} repo at {:?}{}", verb, repo_dir, if quarantine { " into quarantine" } else { "" }),
+                        None => println!("No repo found for workspace id {}", workspace_id),
+                    }
+                }
+                None => println!("Invalid workspace id {}", workspace_id),
+            }
+        }
+        Some(Command::Backup { dest }) => {
+            println!("Backing up to {:?}...", &dest);
+            let manifest = backup(&backend, &caller, &git_root, &dest).await?;
+            println!("Backed up database and {} repos", manifest.repos.len());
+        }
+        Some(Command::Restore { .. }) => unreachable!(),
+        Some(Command::Export { workspace_id, output }) => {
+            let transfer = WorkspaceTransferBackend::export_workspaces(&backend, &caller, &workspace_id).await?;
+            let json = serde_json::to_string_pretty(&transfer)?;
+            match output {
+                Some(output) => {
+                    std::fs::write(&output, json)?;
+                    println!("Exported {} workspaces to {:?}", transfer.workspaces.len(), &output);
+                }
+                None => println!("{}", json),
+            }
+        }
+        Some(Command::Import { input }) => {
+            println!("Importing workspaces from {:?}...", &input);
+            let transfer = serde_json::from_slice(&std::fs::read(&input)?)?;
+            let summary = WorkspaceTransferBackend::import_workspaces(&backend, &caller, &transfer).await?;
+            for (exported_id, id) in summary.imported.iter() {
+                println!("imported {} as workspace id {}", exported_id, id);
+            }
+            for (exported_id, id) in summary.duplicates.iter() {
+                println!("skipped {}, already registered as workspace id {}", exported_id, id);
+            }
+            println!("Imported workspaces: {}", summary);
+        }
+        Some(Command::Fsck { fix }) => {
+            // one json object per issue found
+            for finding in fsck(&backend, &caller, &git_root, fix).await? {
+                println!("{}", serde_json::to_string(&finding)?);
+            }
+        }
         Some(Command::Sync { workspace_id, log }) => {
             if log {
                 println!("Listing of sync logs for workspace with id {}", workspace_id);
-                let recs = WorkspaceSyncBackend::get_workspaces_sync_records(&backend, workspace_id).await?;
+                let recs = WorkspaceSyncBackend::get_workspaces_sync_records(&backend, &caller, workspace_id).await?;
                 println!("start - end - status");
                 for rec in recs {
                     println!("{}", rec);
@@ -124,21 +622,39 @@ async fn main(args: Args) -> anyhow::Result<()> {
             }
             else {
                 println!("Syncing commits for workspace with id {}...", workspace_id);
-                let workspace = WorkspaceBackend::get_workspace_by_id(&backend, workspace_id).await?;
-                let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace);
+                let workspace = WorkspaceBackend::get_workspace_by_id(&backend, &caller, workspace_id).await?;
+                let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace, caller);
                 git_sync_workspace(&git_pmr_accessor).await?;
             }
         }
+        Some(Command::Maintain { workspace_id, log }) => {
+            if log {
+                println!("Listing of maintenance logs for workspace with id {}", workspace_id);
+                let recs = WorkspaceMaintenanceBackend::get_workspace_maintenance_records(&backend, &caller, workspace_id).await?;
+                println!("start - end - size before - size after");
+                for rec in recs {
+                    println!("{}", rec);
+                }
+            }
+            else {
+                println!("Repacking repository for workspace with id {}...", workspace_id);
+                let workspace = WorkspaceBackend::get_workspace_by_id(&backend, &caller, workspace_id).await?;
+                let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace, caller);
+                let size = git_maintain_workspace(&git_pmr_accessor).await?;
+                println!("Repository is now {} bytes", size);
+            }
+        }
         Some(Command::Tags { workspace_id, index }) => {
             if index {
                 println!("Indexing tags for workspace with id {}...", workspace_id);
-                let workspace = WorkspaceBackend::get_workspace_by_id(&backend, workspace_id).await?;
-                let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace);
-                index_tags(&git_pmr_accessor).await?;
+                let workspace = WorkspaceBackend::get_workspace_by_id(&backend, &caller, workspace_id).await?;
+                let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace, caller);
+                let summary = index_tags(&git_pmr_accessor).await?;
+                println!("Indexed tags: {}", summary);
             }
             else {
                 println!("Listing of indexed tags workspace with id {}", workspace_id);
-                let recs = WorkspaceTagBackend::get_workspace_tags(&backend, workspace_id).await?;
+                let recs = WorkspaceTagBackend::get_workspace_tags(&backend, &caller, workspace_id).await?;
                 println!("commit_id - tag");
                 for rec in recs {
                     println!("{}", rec);
@@ -146,18 +662,24 @@ async fn main(args: Args) -> anyhow::Result<()> {
             }
         }
         Some(Command::Blob { workspace_id, obj_id }) => {
-            let workspace = WorkspaceBackend::get_workspace_by_id(&backend, workspace_id).await?;
-            let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace);
+            let workspace = WorkspaceBackend::get_workspace_by_id(&backend, &caller, workspace_id).await?;
+            let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace, caller);
             get_obj_by_spec(&git_pmr_accessor, &obj_id).await?;
         }
-        Some(Command::Info { workspace_id, commit_id, path, raw }) => {
-            let workspace = WorkspaceBackend::get_workspace_by_id(&backend, workspace_id).await?;
-            let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace);
+        Some(Command::Info { workspace_id, commit_id, path, raw, range }) => {
+            let workspace = WorkspaceBackend::get_workspace_by_id(&backend, &caller, workspace_id).await?;
+            let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace, caller);
             if (raw) {
-                process_pathinfo(
-                    &git_pmr_accessor, commit_id.as_deref(), path.as_deref(),
-                    |git_result_set| stream_git_result_set_blob(io::stdout(), git_result_set)
-                ).await?.unwrap();
+                let stat = stat_blob(&git_pmr_accessor, commit_id.as_deref(), path.as_deref().unwrap_or("")).await?;
+                let range = match range {
+                    Some(range) => Some(ByteRange::parse(&range, stat.size)?),
+                    None => None,
+                };
+                log::info!("blob {} of {} bytes, ETag {}", &stat.id, stat.size, &stat.etag);
+                if let Some(range) = range {
+                    log::info!("writing bytes {}-{}/{}", range.start, range.end, stat.size);
+                }
+                stream_blob_range(&git_pmr_accessor, &stat.id, range, io::stdout()).await?;
             }
             else {
                 process_pathinfo(
@@ -166,14 +688,279 @@ async fn main(args: Args) -> anyhow::Result<()> {
                 ).await?;
             }
         }
-        None => {
-            println!("Printing list of all workspaces");
-            let recs = WorkspaceBackend::list_workspaces(&backend).await?;
-            println!("id - url - description");
+        Some(Command::Tree { workspace_id, commit_id, path }) => {
+            let workspace = WorkspaceBackend::get_workspace_by_id(&backend, &caller, workspace_id).await?;
+            let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace, caller);
+            let listing = list_tree(&git_pmr_accessor, commit_id.as_deref(), path.as_deref()).await?;
+            println!("mode - kind - size - path - last commit");
+            for entry in listing.iter() {
+                println!("{}", entry);
+            }
+        }
+        Some(Command::History { workspace_id, path, commit_id }) => {
+            let workspace = WorkspaceBackend::get_workspace_by_id(&backend, &caller, workspace_id).await?;
+            let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace, caller);
+            let history = file_history(&git_pmr_accessor, commit_id.as_deref(), path.as_deref()).await?;
+            println!("commit - time - author - path - summary");
+            for entry in history {
+                println!("{}", entry);
+            }
+        }
+        Some(Command::Blame { workspace_id, path, commit_id }) => {
+            let workspace = WorkspaceBackend::get_workspace_by_id(&backend, &caller, workspace_id).await?;
+            let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace, caller);
+            let lines = blame(&git_pmr_accessor, commit_id.as_deref(), &path).await?;
+            println!("commit - author - line - content");
+            for line in lines {
+                println!("{}", line);
+            }
+        }
+        Some(Command::Grep {
+            workspace_id, pattern, commit_id, path, literal, ignore_case, kind, context, max_matches
+        }) => {
+            let workspace = WorkspaceBackend::get_workspace_by_id(&backend, &caller, workspace_id).await?;
+            let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace, caller);
+            let query = GrepQuery {
+                pattern: pattern,
+                literal: literal,
+                ignore_case: ignore_case,
+                path: path,
+                kind: kind,
+                context: context,
+                max_matches: max_matches,
+            };
+            let results = grep(&git_pmr_accessor, commit_id.as_deref(), &query).await?;
+            println!("Searching workspace with id {} at {}", workspace_id, &results.commit_id);
+            for (i, m) in results.matches.iter().enumerate() {
+                // the same separator as grep uses between groups of context
+                if context > 0 && i > 0 {
+                    println!("--");
+                }
+                for (j, line) in m.before.iter().enumerate() {
+                    println!("{}-{}-{}", &m.path, m.line_no - m.before.len() + j, line);
+                }
+                println!("{}", m);
+                for (j, line) in m.after.iter().enumerate() {
+                    println!("{}-{}-{}", &m.path, m.line_no + 1 + j, line);
+                }
+            }
+            if results.truncated {
+                println!("Stopped after {} matches", max_matches);
+            }
+            for path in results.skipped.iter() {
+                println!("Not searched (too large or not UTF-8): {}", path);
+            }
+        }
+        Some(Command::Metadata { workspace_id, commit_id, index }) => {
+            let workspace = WorkspaceBackend::get_workspace_by_id(&backend, &caller, workspace_id).await?;
+            let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace, caller);
+            if index {
+                println!("Extracting metadata for workspace with id {}...", workspace_id);
+                let count = index_metadata(&git_pmr_accessor, commit_id.as_deref()).await?;
+                println!("Extracted {} triples", count);
+            }
+            else {
+                let commit_id = resolve_commit_id(&git_pmr_accessor, commit_id.as_deref()).await?;
+                println!("Listing of metadata for workspace with id {} at {}", workspace_id, &commit_id);
+                let recs = WorkspaceMetadataBackend::get_workspace_metadata(
+                    git_pmr_accessor.backend(), git_pmr_accessor.caller(), workspace_id, &commit_id).await?;
+                println!("path - subject - predicate - object");
+                for rec in recs {
+                    println!("{}", rec);
+                }
+            }
+        }
+        Some(Command::Imports { workspace_id, commit_id, path, index, reverse }) => {
+            if reverse {
+                println!("Listing of models importing from workspace with id {}", workspace_id);
+                let recs = WorkspaceImportBackend::get_workspace_importers(
+                    &backend, &caller, workspace_id, path.as_deref()).await?;
+                println!("workspace:commit:path - href - target");
+                for rec in recs {
+                    println!("{}", rec);
+                }
+            }
+            else {
+                let workspace = WorkspaceBackend::get_workspace_by_id(&backend, &caller, workspace_id).await?;
+                let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace, caller);
+                if index {
+                    println!("Indexing imports for workspace with id {}...", workspace_id);
+                    let count = index_imports(&git_pmr_accessor, commit_id.as_deref()).await?;
+                    println!("Indexed {} imports", count);
+                }
+                else {
+                    let commit_id = resolve_commit_id(&git_pmr_accessor, commit_id.as_deref()).await?;
+                    println!("Import tree for workspace with id {} at {}", workspace_id, &commit_id);
+                    print_import_tree(
+                        git_pmr_accessor.backend(), git_pmr_accessor.caller(), workspace_id, &commit_id, path.as_deref()).await?;
+                }
+            }
+        }
+        Some(Command::User { cmd }) => {
+            match cmd {
+                Some(UserCommand::Add { name }) => {
+                    let user_id = UserBackend::add_user(&backend, &caller, &name).await?;
+                    println!("Added user '{}' with id {}", &name, user_id);
+                }
+                None => {
+                    println!("Printing list of all users");
+                    let recs = UserBackend::list_users(&backend).await?;
+                    println!("id - name - created");
+                    for rec in recs {
+                        println!("{}", rec);
+                    }
+                }
+            }
+        }
+        Some(Command::Acl { workspace_id, cmd }) => {
+            match cmd {
+                Some(AclCommand::Grant { user, role }) => {
+                    let user_id = UserBackend::get_user_by_name(&backend, &user).await?.id;
+                    WorkspaceAclBackend::grant_workspace_role(&backend, &caller, workspace_id, user_id, role).await?;
+                    println!("Granted {:?} of workspace id {} to '{}'", role, workspace_id, &user);
+                }
+                Some(AclCommand::Revoke { user }) => {
+                    let user_id = UserBackend::get_user_by_name(&backend, &user).await?.id;
+                    if WorkspaceAclBackend::revoke_workspace_role(&backend, &caller, workspace_id, user_id).await? {
+                        println!("Revoked access to workspace id {} from '{}'", workspace_id, &user);
+                    }
+                    else {
+                        println!("User '{}' had no access to workspace id {}", &user, workspace_id);
+                    }
+                }
+                None => {
+                    println!("Listing of access for workspace with id {}", workspace_id);
+                    let recs = WorkspaceAclBackend::get_workspace_acl(&backend, &caller, workspace_id).await?;
+                    println!("user_id - name - role");
+                    for rec in recs {
+                        println!("{}", rec);
+                    }
+                }
+            }
+        }
+        Some(Command::Keyword { workspace_id, cmd }) => {
+            let workspace = WorkspaceBackend::get_workspace_by_id(&backend, &caller, workspace_id).await?;
+            match cmd {
+                Some(KeywordCommand::Add { keyword }) => {
+                    if WorkspaceKeywordBackend::add_workspace_keyword(&backend, &caller, workspace_id, &keyword).await? {
+                        println!("Added keyword '{}' to workspace id {}", &keyword, workspace_id);
+                    }
+                    else {
+                        println!("Workspace id {} already has keyword '{}'", workspace_id, &keyword);
+                    }
+                }
+                Some(KeywordCommand::Remove { keyword }) => {
+                    if WorkspaceKeywordBackend::remove_workspace_keyword(&backend, &caller, workspace_id, &keyword).await? {
+                        println!("Removed keyword '{}' from workspace id {}", &keyword, workspace_id);
+                    }
+                    else {
+                        println!("Workspace id {} had no keyword '{}'", workspace_id, &keyword);
+                    }
+                }
+                Some(KeywordCommand::Extract { commit_id }) => {
+                    println!("Extracting keywords for workspace with id {}...", workspace_id);
+                    let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace, caller);
+                    let count = index_keywords(&git_pmr_accessor, commit_id.as_deref()).await?;
+                    println!("Extracted {} keywords", count);
+                }
+                None => {
+                    println!("Listing of keywords for workspace with id {}", workspace_id);
+                    let recs = WorkspaceKeywordBackend::get_workspace_keywords(&backend, &caller, workspace_id).await?;
+                    println!("keyword - source");
+                    for rec in recs {
+                        println!("{}", rec);
+                    }
+                }
+            }
+        }
+        Some(Command::Keywords) => {
+            println!("Listing of keywords");
+            let recs = WorkspaceKeywordBackend::list_keywords(&backend, &caller).await?;
+            println!("keyword - workspaces");
+            for rec in recs {
+                println!("{}", rec);
+            }
+        }
+        Some(Command::Audit { workspace_id, since, until }) => {
+            println!("Listing of audit events");
+            let recs = AuditBackend::get_audit_events(&backend, &caller, workspace_id, since, until).await?;
+            println!("time - actor - action - target - before -> after");
             for rec in recs {
                 println!("{}", rec);
             }
         }
+        Some(Command::Token { cmd }) => {
+            match cmd {
+                TokenCommand::Create { user, scope, expires, description } => {
+                    let user_id = UserBackend::get_user_by_name(&backend, &user).await?.id;
+                    let token = UserTokenBackend::create_token(
+                        &backend, &caller, user_id, scope, expires, &description).await?;
+                    println!("Created token for '{}'; it will not be shown again:", &user);
+                    println!("{}", token);
+                }
+                TokenCommand::List { user } => {
+                    let user_id = UserBackend::get_user_by_name(&backend, &user).await?.id;
+                    println!("Listing of tokens for user '{}'", &user);
+                    let recs = UserTokenBackend::list_tokens(&backend, &caller, user_id).await?;
+                    println!("id - scope - expires - revoked - description");
+                    for rec in recs {
+                        println!("{}", rec);
+                    }
+                }
+                TokenCommand::Revoke { token_id } => {
+                    if UserTokenBackend::revoke_token(&backend, &caller, token_id).await? {
+                        println!("Revoked token id {}", token_id);
+                    }
+                    else {
+                        println!("Invalid or already revoked token id {}", token_id);
+                    }
+                }
+            }
+        }
+        Some(Command::Search { query, content, limit }) => {
+            if content {
+                println!("Searching file contents for '{}'", &query);
+                let recs = SearchBackend::search_workspace_blobs(&backend, &caller, &query, limit).await?;
+                println!("id - commit_id:path - snippet");
+                for rec in recs {
+                    println!("{}", rec);
+                }
+            }
+            else {
+                println!("Searching workspaces for '{}'", &query);
+                let recs = SearchBackend::search_workspaces(&backend, &caller, &query, limit).await?;
+                println!("id - url - snippet");
+                for rec in recs {
+                    println!("{}", rec);
+                }
+            }
+        }
+        Some(Command::SearchIndex { workspace_id }) => {
+            println!("Indexing file contents for workspace with id {}...", workspace_id);
+            let workspace = WorkspaceBackend::get_workspace_by_id(&backend, &caller, workspace_id).await?;
+            let git_pmr_accessor = GitPmrAccessor::new(backend, git_root, workspace, caller);
+            let count = index_search_blobs(&git_pmr_accessor).await?;
+            println!("Indexed {} files", count);
+        }
+        Some(Command::List { filter, keyword, since, until, sort, desc, limit, offset }) => {
+            let query = WorkspaceQuery {
+                text: filter,
+                keyword: keyword,
+                created_from: since,
+                created_before: until,
+                sort: sort,
+                descending: desc,
+                limit: Some(limit),
+                offset: offset,
+            };
+            let page = WorkspaceBackend::query_workspaces(&backend, &caller, &query).await?;
+            print_workspace_page(&page, offset);
+        }
+        None => {
+            println!("Printing list of all workspaces");
+            let page = WorkspaceBackend::query_workspaces(&backend, &caller, &WorkspaceQuery::default()).await?;
+            print_workspace_page(&page, 0);
+        }
     }
 
     Ok(())
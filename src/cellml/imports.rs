@@ -0,0 +1,75 @@
+use roxmltree::Document;
+
+use crate::error::PmrError;
+
+pub const CELLML_1_0_NS: &str = "http://www.cellml.org/cellml/1.0#";
+pub const CELLML_1_1_NS: &str = "http://www.cellml.org/cellml/1.1#";
+pub const CELLML_2_0_NS: &str = "http://www.cellml.org/cellml/2.0#";
+pub const XLINK_NS: &str = "http://www.w3.org/1999/xlink";
+
+// The xlink:href of every import element in a CellML document, in
+// document order.
+pub fn extract_imports(text: &str) -> Result<Vec<String>, PmrError> {
+    let doc = Document::parse(text)?;
+    Ok(doc.descendants()
+        .filter(|n| n.is_element() && n.tag_name().name() == "import")
+        .filter(|n| match n.tag_name().namespace() {
+            Some(CELLML_1_0_NS) | Some(CELLML_1_1_NS) | Some(CELLML_2_0_NS) => true,
+            _ => false,
+        })
+        .filter_map(|n| n.attribute((XLINK_NS, "href")))
+        .map(String::from)
+        .collect())
+}
+
+// Resolve href relative to the file at path, both within the same tree.
+// Returns None for absolute URLs or for references leading out of the tree.
+pub fn resolve_href(path: &str, href: &str) -> Option<String> {
+    if href.contains("://") || href.starts_with('/') {
+        return None;
+    }
+    // drop any fragment or query
+    let href = href.split(|c| c == '#' || c == '?').next().unwrap_or("");
+    let mut parts: Vec<&str> = path.split('/').collect();
+    // remove the file name of the importing model
+    parts.pop();
+    for part in href.split('/') {
+        match part {
+            "" | "." => (),
+            ".." => {
+                parts.pop()?;
+            }
+            _ => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_imports() {
+        let text = r#"<model xmlns="http://www.cellml.org/cellml/1.1#"
+                xmlns:xlink="http://www.w3.org/1999/xlink" name="main">
+            <import xlink:href="a.cellml"/>
+            <import href="no-xlink.cellml"/>
+            <other:import xmlns:other="http://example.com/" xlink:href="other.cellml"/>
+            <import xlink:href="lib/b.cellml#c"/>
+        </model>"#;
+        assert_eq!(extract_imports(text).unwrap(), vec!["a.cellml", "lib/b.cellml#c"]);
+        assert!(matches!(extract_imports("<model><import></model>"), Err(PmrError::InvalidDocument(_))));
+    }
+
+    #[test]
+    fn test_resolve_href() {
+        assert_eq!(resolve_href("main.cellml", "a.cellml").as_deref(), Some("a.cellml"));
+        assert_eq!(resolve_href("x/main.cellml", "./a.cellml#c").as_deref(), Some("x/a.cellml"));
+        assert_eq!(resolve_href("x/main.cellml", "../lib/b.cellml?v=1").as_deref(), Some("lib/b.cellml"));
+        assert_eq!(resolve_href("x/y/main.cellml", "../../a.cellml").as_deref(), Some("a.cellml"));
+        assert_eq!(resolve_href("x/main.cellml", "../../a.cellml"), None);
+        assert_eq!(resolve_href("main.cellml", "/a.cellml"), None);
+        assert_eq!(resolve_href("main.cellml", "http://example.com/a.cellml"), None);
+    }
+}
@@ -0,0 +1,294 @@
+use roxmltree::{Document, Node};
+use std::collections::HashSet;
+
+use crate::error::PmrError;
+
+pub const RDF_NS: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+pub const DC_SUBJECT: &str = "http://purl.org/dc/elements/1.1/subject";
+pub const DCTERMS_SUBJECT: &str = "http://purl.org/dc/terms/subject";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Triple {
+    pub subject: String,
+    pub predicate: String,
+    pub object: String,
+    // whether the object is a literal rather than an IRI or blank node
+    pub literal: bool,
+}
+
+// Extract the triples from every rdf:RDF element found in the document,
+// which may either be a standalone RDF/XML file or a CellML model with
+// embedded metadata.
+//
+// Only the commonly used parts of RDF/XML are handled; IRIs are stored as
+// they appear in the document, blank nodes are labelled as `_:name`.
+pub fn extract_triples(text: &str) -> Result<Vec<Triple>, PmrError> {
+    let doc = Document::parse(text)?;
+    let mut parser = RdfParser {
+        triples: Vec::new(),
+        bnode_count: 0,
+    };
+    for node in doc.descendants().filter(|n| is_rdf(n, "RDF")) {
+        for child in node.children().filter(|n| n.is_element()) {
+            parser.node_element(child);
+        }
+    }
+    Ok(parser.triples)
+}
+
+fn is_rdf(node: &Node, name: &str) -> bool {
+    node.is_element()
+        && node.tag_name().namespace() == Some(RDF_NS)
+        && node.tag_name().name() == name
+}
+
+fn rdf_attr<'a>(node: &Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attribute((RDF_NS, name))
+}
+
+fn qualified_name(node: &Node) -> String {
+    format!("{}{}", node.tag_name().namespace().unwrap_or(""), node.tag_name().name())
+}
+
+struct RdfParser {
+    triples: Vec<Triple>,
+    bnode_count: usize,
+}
+
+impl RdfParser {
+    fn new_bnode(&mut self) -> String {
+        self.bnode_count += 1;
+        format!("_:b{}", self.bnode_count)
+    }
+
+    fn push(&mut self, subject: &str, predicate: String, object: String, literal: bool) {
+        self.triples.push(Triple {
+            subject: subject.to_string(),
+            predicate: predicate,
+            object: object,
+            literal: literal,
+        });
+    }
+
+    // Returns the subject of the node element.
+    fn node_element(&mut self, node: Node) -> String {
+        let subject = if let Some(about) = rdf_attr(&node, "about") {
+            about.to_string()
+        } else if let Some(id) = rdf_attr(&node, "ID") {
+            format!("#{}", id)
+        } else if let Some(node_id) = rdf_attr(&node, "nodeID") {
+            format!("_:{}", node_id)
+        } else {
+            self.new_bnode()
+        };
+
+        if !is_rdf(&node, "Description") {
+            self.push(&subject, format!("{}type", RDF_NS), qualified_name(&node), false);
+        }
+        self.property_attributes(&subject, node);
+        self.property_elements(&subject, node);
+        subject
+    }
+
+    fn property_attributes(&mut self, subject: &str, node: Node) {
+        for attr in node.attributes() {
+            match attr.namespace() {
+                Some(RDF_NS) => {
+                    if attr.name() == "type" {
+                        self.push(subject, format!("{}type", RDF_NS), attr.value().to_string(), false);
+                    }
+                }
+                // unqualified and xml: attributes are not properties
+                None | Some("http://www.w3.org/XML/1998/namespace") => (),
+                Some(ns) => {
+                    self.push(subject, format!("{}{}", ns, attr.name()), attr.value().to_string(), true);
+                }
+            }
+        }
+    }
+
+    fn property_elements(&mut self, subject: &str, node: Node) {
+        let mut li_count = 0;
+        for prop in node.children().filter(|n| n.is_element()) {
+            let predicate = if is_rdf(&prop, "li") {
+                li_count += 1;
+                format!("{}_{}", RDF_NS, li_count)
+            } else {
+                qualified_name(&prop)
+            };
+
+            let (object, literal) = if let Some(resource) = rdf_attr(&prop, "resource") {
+                (resource.to_string(), false)
+            } else if let Some(node_id) = rdf_attr(&prop, "nodeID") {
+                (format!("_:{}", node_id), false)
+            } else {
+                match rdf_attr(&prop, "parseType") {
+                    Some("Resource") => {
+                        let bnode = self.new_bnode();
+                        self.property_elements(&bnode, prop);
+                        (bnode, false)
+                    }
+                    Some(_) => (text_content(prop), true),
+                    None => match prop.children().find(|n| n.is_element()) {
+                        Some(child) => (self.node_element(child), false),
+                        None => {
+                            let has_properties = prop.attributes().iter()
+                                .any(|a| a.namespace().is_some() && a.namespace() != Some(RDF_NS));
+                            if has_properties {
+                                // empty property element with property attributes
+                                let bnode = self.new_bnode();
+                                self.property_attributes(&bnode, prop);
+                                (bnode, false)
+                            } else {
+                                (text_content(prop), true)
+                            }
+                        }
+                    },
+                }
+            };
+            self.push(subject, predicate, object, literal);
+        }
+    }
+}
+
+fn text_content(node: Node) -> String {
+    node.descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+// The keywords given as the subject of anything described by the triples,
+// either directly as a literal or as the members of a container, which is
+// how the CellML metadata specification has them, e.g.
+//
+//     <dc:subject rdf:parseType="Resource">
+//       <bqs:subject_type>keyword</bqs:subject_type>
+//       <rdf:value><rdf:Bag><rdf:li>cardiac</rdf:li></rdf:Bag></rdf:value>
+//     </dc:subject>
+pub fn extract_keywords(triples: &[Triple]) -> Vec<String> {
+    let mut keywords = Vec::new();
+    let mut visited = HashSet::new();
+    for triple in triples {
+        if triple.predicate == DC_SUBJECT || triple.predicate == DCTERMS_SUBJECT {
+            collect_literals(triples, triple, &mut visited, &mut keywords);
+        }
+    }
+    keywords
+}
+
+// The literal objects reached from the triple through rdf:value and the
+// members of containers; any other resource, e.g. an IRI naming a term in
+// some vocabulary, is not a keyword. The visited nodes are tracked as
+// nothing stops a document having cycles.
+fn collect_literals<'a>(
+    triples: &'a [Triple], triple: &'a Triple, visited: &mut HashSet<&'a str>, literals: &mut Vec<String>
+) {
+    if triple.literal {
+        if !triple.object.is_empty() {
+            literals.push(triple.object.clone());
+        }
+        return;
+    }
+    if !visited.insert(&triple.object) {
+        return;
+    }
+    for member in triples.iter().filter(|member| member.subject == triple.object) {
+        let is_member = member.predicate.strip_prefix(RDF_NS)
+            .map_or(false, |name| name == "value" || name.starts_with('_'));
+        if is_member {
+            collect_literals(triples, member, visited, literals);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triple(subject: &str, predicate: &str, object: &str, literal: bool) -> Triple {
+        Triple {
+            subject: subject.to_string(),
+            predicate: predicate.to_string(),
+            object: object.to_string(),
+            literal: literal,
+        }
+    }
+
+    #[test]
+    fn test_extract_triples() {
+        let dc = "http://purl.org/dc/elements/1.1/";
+        let vc = "http://www.w3.org/2001/vcard-rdf/3.0#";
+        let triples = extract_triples(r#"<?xml version="1.0"?>
+<model xmlns="http://www.cellml.org/cellml/1.1#" name="m"
+    xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:vc="http://www.w3.org/2001/vcard-rdf/3.0#">
+  <rdf:RDF>
+    <rdf:Description rdf:about="" dc:title="A model">
+      <dc:creator rdf:parseType="Resource">
+        <vc:FN>Jane</vc:FN>
+      </dc:creator>
+      <dc:source rdf:resource="http://example.com/"/>
+      <dc:relation>
+        <dc:Agent rdf:ID="a" vc:N="x"/>
+      </dc:relation>
+      <dc:format rdf:nodeID="f"/>
+      <dc:description rdf:parseType="Literal"><b>bold</b> text</dc:description>
+    </rdf:Description>
+  </rdf:RDF>
+</model>"#).unwrap();
+        assert_eq!(triples, vec![
+            triple("", &format!("{}title", dc), "A model", true),
+            triple("_:b1", &format!("{}FN", vc), "Jane", true),
+            triple("", &format!("{}creator", dc), "_:b1", false),
+            triple("", &format!("{}source", dc), "http://example.com/", false),
+            triple("#a", &format!("{}type", RDF_NS), &format!("{}Agent", dc), false),
+            triple("#a", &format!("{}N", vc), "x", true),
+            triple("", &format!("{}relation", dc), "#a", false),
+            triple("", &format!("{}format", dc), "_:f", false),
+            triple("", &format!("{}description", dc), "bold text", true),
+        ]);
+
+        assert_eq!(extract_triples("<model/>").unwrap(), vec![]);
+        assert!(matches!(extract_triples("<rdf:RDF>"), Err(PmrError::InvalidDocument(_))));
+    }
+
+    #[test]
+    fn test_extract_keywords() {
+        let triples = extract_triples(r#"<?xml version="1.0"?>
+<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:dcterms="http://purl.org/dc/terms/"
+    xmlns:bqs="http://www.cellml.org/bqs/1.0#">
+  <rdf:Description rdf:about="">
+    <dc:subject rdf:parseType="Resource">
+      <bqs:subject_type>keyword</bqs:subject_type>
+      <rdf:value>
+        <rdf:Bag>
+          <rdf:li>cardiac</rdf:li>
+          <rdf:li>electrophysiology</rdf:li>
+        </rdf:Bag>
+      </rdf:value>
+    </dc:subject>
+    <dcterms:subject>calcium</dcterms:subject>
+    <dc:subject rdf:resource="http://identifiers.org/go/GO:0006936"/>
+    <dc:title>not a keyword</dc:title>
+  </rdf:Description>
+</rdf:RDF>"#).unwrap();
+        assert_eq!(extract_keywords(&triples), vec!["cardiac", "electrophysiology", "calcium"]);
+    }
+
+    #[test]
+    fn test_extract_keywords_cycle() {
+        let triples = vec![
+            triple("", DC_SUBJECT, "_:a", false),
+            triple("_:a", &format!("{}value", RDF_NS), "_:b", false),
+            triple("_:b", &format!("{}_1", RDF_NS), "_:a", false),
+            triple("_:b", &format!("{}_2", RDF_NS), "heart", true),
+        ];
+        assert_eq!(extract_keywords(&triples), vec!["heart"]);
+    }
+}
@@ -0,0 +1,109 @@
+use std::fmt;
+
+use crate::model::workspace_url::WorkspaceUrlError;
+
+// The ways in which the model and repo operations fail, so that callers
+// can tell them apart (e.g. to map them to HTTP status codes) without
+// having to parse messages.
+#[derive(Debug)]
+pub enum PmrError {
+    // also what anything hidden from the caller is reported as
+    NotFound(String),
+    PermissionDenied(String),
+    InvalidInput(String),
+    // a file that could not be parsed or fails its checksum, e.g. a model
+    // in a repo or a manifest
+    InvalidDocument(String),
+    // does not name a commit
+    InvalidRevision(String),
+    // no such path in the tree of the commit
+    PathNotFound(String),
+    NotABlob(String),
+    NotATree(String),
+    // the byte range cannot be satisfied by a blob of this size
+    RangeNotSatisfiable(u64),
+    // the workspace cannot be synced in its current state
+    SyncConflict(String),
+    // the remote could not be cloned or fetched
+    SyncFailed(String),
+    // housekeeping of the repo, i.e. git gc, failed
+    MaintenanceFailed(String),
+    Url(WorkspaceUrlError),
+    Backend(sqlx::Error),
+    Git(git2::Error),
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for PmrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PmrError::NotFound(what) => write!(f, "{} not found", what),
+            PmrError::PermissionDenied(msg) => write!(f, "permission denied: {}", msg),
+            PmrError::InvalidInput(msg) => write!(f, "{}", msg),
+            PmrError::InvalidDocument(msg) => write!(f, "invalid document: {}", msg),
+            PmrError::InvalidRevision(spec) => write!(f, "'{}' does not refer to a valid commit", spec),
+            PmrError::PathNotFound(path) => write!(f, "path '{}' not found", path),
+            PmrError::NotABlob(path) => write!(f, "'{}' is not a git blob", path),
+            PmrError::NotATree(path) => write!(f, "'{}' is not a git tree", path),
+            PmrError::RangeNotSatisfiable(size) => write!(f, "range not satisfiable for {} bytes", size),
+            PmrError::SyncConflict(msg) => write!(f, "{}", msg),
+            PmrError::SyncFailed(msg) => write!(f, "{}", msg),
+            PmrError::MaintenanceFailed(msg) => write!(f, "{}", msg),
+            PmrError::Url(e) => write!(f, "{}", e),
+            PmrError::Backend(e) => write!(f, "{}", e),
+            PmrError::Git(e) => write!(f, "{}", e),
+            PmrError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PmrError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PmrError::Url(e) => Some(e),
+            PmrError::Backend(e) => Some(e),
+            PmrError::Git(e) => Some(e),
+            PmrError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for PmrError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => PmrError::NotFound("record".to_string()),
+            e => PmrError::Backend(e),
+        }
+    }
+}
+
+impl From<git2::Error> for PmrError {
+    fn from(e: git2::Error) -> Self {
+        PmrError::Git(e)
+    }
+}
+
+impl From<std::io::Error> for PmrError {
+    fn from(e: std::io::Error) -> Self {
+        PmrError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for PmrError {
+    fn from(e: serde_json::Error) -> Self {
+        PmrError::InvalidDocument(e.to_string())
+    }
+}
+
+impl From<roxmltree::Error> for PmrError {
+    fn from(e: roxmltree::Error) -> Self {
+        PmrError::InvalidDocument(e.to_string())
+    }
+}
+
+impl From<WorkspaceUrlError> for PmrError {
+    fn from(e: WorkspaceUrlError) -> Self {
+        PmrError::Url(e)
+    }
+}
@@ -1,11 +1,32 @@
+pub mod cellml {
+    pub mod imports;
+    pub mod rdf;
+}
 pub mod repo {
+    pub mod backup;
+    pub mod cache;
+    pub mod filetype;
+    pub mod fsck;
     pub mod git;
+    pub mod register;
 }
+pub mod error;
 pub mod model {
+    pub mod audit;
     pub mod backend;
+    pub mod search;
+    pub mod user;
+    pub mod user_token;
     pub mod workspace;
+    pub mod workspace_acl;
+    pub mod workspace_import;
+    pub mod workspace_keyword;
+    pub mod workspace_maintenance;
+    pub mod workspace_metadata;
     pub mod workspace_sync;
     pub mod workspace_tag;
+    pub mod workspace_transfer;
+    pub mod workspace_url;
 }
 pub mod utils;
 
@@ -0,0 +1,113 @@
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use serde_json::Value;
+use sqlx::{Sqlite, Transaction};
+use std::fmt;
+
+use crate::error::PmrError;
+use crate::model::backend::SqliteBackend;
+use crate::model::user::Caller;
+use crate::model::workspace_acl::{
+    WorkspaceAclBackend,
+    WorkspaceRole,
+};
+
+#[async_trait]
+pub trait AuditBackend {
+    // Events recorded from since up to but not including until.
+    async fn get_audit_events(
+        &self, caller: &Caller, workspace_id: Option<i64>, since: Option<i64>, until: Option<i64>
+    ) -> Result<Vec<AuditEventRecord>, PmrError>;
+}
+
+pub struct AuditEventRecord {
+    pub id: i64,
+    pub created: i64,
+    pub actor: String,
+    pub action: String,
+    pub workspace_id: Option<i64>,
+    pub target: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl std::fmt::Display for AuditEventRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {} - {} - {} - {} -> {}",
+            Utc.timestamp_opt(self.created, 0).unwrap().to_rfc3339(),
+            &self.actor,
+            &self.action,
+            &self.target,
+            self.before.as_deref().unwrap_or("<nil>"),
+            self.after.as_deref().unwrap_or("<nil>"),
+        )
+    }
+}
+
+// Record an event as part of the transaction making the change, so that
+// the change and its event are either both committed or neither are.
+pub(crate) async fn record_event(
+    tx: &mut Transaction<'_, Sqlite>,
+    caller: &Caller,
+    action: &str,
+    workspace_id: Option<i64>,
+    target: &str,
+    before: Option<Value>,
+    after: Option<Value>,
+) -> Result<(), PmrError> {
+    let ts = Utc::now().timestamp();
+    let actor = caller.to_string();
+    let before = before.map(|v| v.to_string());
+    let after = after.map(|v| v.to_string());
+    sqlx::query!(
+        r#"
+INSERT INTO audit_event ( created, actor, action, workspace_id, target, before, after )
+VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7 )
+        "#,
+        ts,
+        actor,
+        action,
+        workspace_id,
+        target,
+        before,
+        after,
+    )
+    .execute(&mut *tx)
+    .await?;
+    Ok(())
+}
+
+#[async_trait]
+impl AuditBackend for SqliteBackend {
+    async fn get_audit_events(
+        &self, caller: &Caller, workspace_id: Option<i64>, since: Option<i64>, until: Option<i64>
+    ) -> Result<Vec<AuditEventRecord>, PmrError> {
+        // owners may see the history of their workspace; only the system
+        // may see everything
+        match workspace_id {
+            Some(workspace_id) => WorkspaceAclBackend::check_workspace_permission(
+                self, caller, workspace_id, WorkspaceRole::Owner).await?,
+            None if caller != &Caller::System => return Err(PmrError::PermissionDenied(
+                format!("{:?} may not list all events", caller))),
+            None => (),
+        };
+        let recs = sqlx::query_as!(AuditEventRecord,
+            r#"
+SELECT id, created, actor, action, workspace_id, target, before, after
+FROM audit_event
+WHERE (?1 IS NULL OR workspace_id = ?1)
+    AND (?2 IS NULL OR created >= ?2)
+    AND (?3 IS NULL OR created < ?3)
+ORDER BY id
+            "#,
+            workspace_id,
+            since,
+            until,
+        )
+        .fetch_all(&*self.pool)
+        .await?;
+        Ok(recs)
+    }
+}
@@ -0,0 +1,215 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_json::json;
+use std::fmt;
+
+use crate::error::PmrError;
+use crate::model::audit::record_event;
+use crate::model::backend::SqliteBackend;
+use crate::model::user::Caller;
+use crate::model::workspace_acl::{
+    WorkspaceAclBackend,
+    WorkspaceRole,
+};
+
+#[async_trait]
+pub trait SearchBackend {
+    async fn search_workspaces(&self, caller: &Caller, query: &str, limit: i64) -> Result<Vec<WorkspaceSearchRecord>, PmrError>;
+    async fn index_workspace_blobs(
+        &self, caller: &Caller, workspace_id: i64, commit_id: &str, blobs: &[(String, String)]
+    ) -> Result<u64, PmrError>;
+    async fn search_workspace_blobs(&self, caller: &Caller, query: &str, limit: i64) -> Result<Vec<BlobSearchRecord>, PmrError>;
+}
+
+pub struct WorkspaceSearchRecord {
+    pub workspace_id: i64,
+    pub url: String,
+    pub snippet: String,
+    pub rank: f64,
+}
+
+impl std::fmt::Display for WorkspaceSearchRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {} - {}",
+            self.workspace_id,
+            &self.url,
+            &self.snippet,
+        )
+    }
+}
+
+pub struct BlobSearchRecord {
+    pub workspace_id: i64,
+    pub commit_id: String,
+    pub path: String,
+    pub snippet: String,
+    pub rank: f64,
+}
+
+impl std::fmt::Display for BlobSearchRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {}:{} - {}",
+            self.workspace_id,
+            &self.commit_id,
+            &self.path,
+            &self.snippet,
+        )
+    }
+}
+
+// Turn what was typed into an FTS5 query matching every term in it, with
+// each term quoted so that characters such as '-', ':' or '"' are taken
+// as is rather than as query syntax; a trailing '*' still matches terms
+// starting with what precedes it.
+fn fts_query(query: &str) -> Result<String, PmrError> {
+    let terms: Vec<String> = query.split_whitespace()
+        .filter_map(|term| {
+            let (term, prefix) = match term.strip_suffix('*') {
+                Some(term) => (term, "*"),
+                None => (term, ""),
+            };
+            if term.is_empty() {
+                None
+            }
+            else {
+                Some(format!("\"{}\"{}", term.replace('"', "\"\""), prefix))
+            }
+        })
+        .collect();
+    if terms.is_empty() {
+        return Err(PmrError::InvalidInput("search query may not be empty".to_string()));
+    }
+    Ok(terms.join(" "))
+}
+
+#[async_trait]
+impl SearchBackend for SqliteBackend {
+    async fn search_workspaces(&self, caller: &Caller, query: &str, limit: i64) -> Result<Vec<WorkspaceSearchRecord>, PmrError> {
+        let ts = Utc::now().timestamp();
+        let is_system = caller == &Caller::System;
+        let user_id = caller.user_id();
+        let query = fts_query(query)?;
+        // lower bm25 is a better match
+        let recs = sqlx::query_as!(WorkspaceSearchRecord,
+            r#"
+SELECT
+    workspace.id AS "workspace_id!",
+    workspace.url AS "url!",
+    snippet(workspace_fts, -1, '[', ']', '...', 16) AS "snippet!: String",
+    bm25(workspace_fts) AS "rank!: f64"
+FROM workspace_fts
+JOIN workspace ON workspace.id = workspace_fts.rowid
+WHERE workspace_fts MATCH ?1 AND (
+    ?3 OR (workspace.archived IS NULL AND workspace.id IN (
+        SELECT workspace_id FROM workspace_reader
+        WHERE since <= ?4 AND (user_id IS NULL OR user_id = ?5)
+    ))
+)
+ORDER BY bm25(workspace_fts)
+LIMIT ?2
+            "#,
+            query,
+            limit,
+            is_system,
+            ts,
+            user_id,
+        )
+        .fetch_all(&*self.pool)
+        .await?;
+        Ok(recs)
+    }
+
+    async fn index_workspace_blobs(
+        &self, caller: &Caller, workspace_id: i64, commit_id: &str, blobs: &[(String, String)]
+    ) -> Result<u64, PmrError> {
+        WorkspaceAclBackend::check_workspace_permission(self, caller, workspace_id, WorkspaceRole::Editor).await?;
+        let mut tx = self.pool.begin().await?;
+
+        // only the latest indexed commit for a workspace is kept
+        sqlx::query!(
+            r#"
+DELETE FROM workspace_blob_fts
+WHERE workspace_id = ?1
+            "#,
+            workspace_id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        for (path, content) in blobs {
+            sqlx::query!(
+                r#"
+INSERT INTO workspace_blob_fts ( workspace_id, commit_id, path, content )
+VALUES ( ?1, ?2, ?3, ?4 )
+                "#,
+                workspace_id,
+                commit_id,
+                path,
+                content,
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        record_event(
+            &mut tx, caller, "workspace_blob.index", Some(workspace_id), &format!("workspace:{}", workspace_id),
+            None, Some(json!({ "commit_id": commit_id, "blobs": blobs.len() })),
+        ).await?;
+
+        tx.commit().await?;
+        Ok(blobs.len() as u64)
+    }
+
+    async fn search_workspace_blobs(&self, caller: &Caller, query: &str, limit: i64) -> Result<Vec<BlobSearchRecord>, PmrError> {
+        let ts = Utc::now().timestamp();
+        let is_system = caller == &Caller::System;
+        let user_id = caller.user_id();
+        let query = fts_query(query)?;
+        let recs = sqlx::query_as!(BlobSearchRecord,
+            r#"
+SELECT
+    workspace_blob_fts.workspace_id AS "workspace_id!: i64",
+    workspace_blob_fts.commit_id AS "commit_id!: String",
+    workspace_blob_fts.path AS "path!: String",
+    snippet(workspace_blob_fts, 3, '[', ']', '...', 16) AS "snippet!: String",
+    bm25(workspace_blob_fts) AS "rank!: f64"
+FROM workspace_blob_fts
+JOIN workspace ON workspace.id = workspace_blob_fts.workspace_id
+WHERE workspace_blob_fts MATCH ?1 AND (
+    ?3 OR (workspace.archived IS NULL AND workspace.id IN (
+        SELECT workspace_id FROM workspace_reader
+        WHERE since <= ?4 AND (user_id IS NULL OR user_id = ?5)
+    ))
+)
+ORDER BY bm25(workspace_blob_fts)
+LIMIT ?2
+            "#,
+            query,
+            limit,
+            is_system,
+            ts,
+            user_id,
+        )
+        .fetch_all(&*self.pool)
+        .await?;
+        Ok(recs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fts_query() {
+        assert_eq!(fts_query("cardiac model").unwrap(), r#""cardiac" "model""#);
+        assert_eq!(fts_query("na-k pump:v2").unwrap(), r#""na-k" "pump:v2""#);
+        assert_eq!(fts_query(r#"say "hi""#).unwrap(), r#""say" """hi""""#);
+        assert_eq!(fts_query("card* *").unwrap(), r#""card"*"#);
+        assert!(matches!(fts_query("  "), Err(PmrError::InvalidInput(_))));
+    }
+}
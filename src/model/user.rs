@@ -0,0 +1,131 @@
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use serde_json::json;
+use std::fmt;
+
+use crate::error::PmrError;
+use crate::model::audit::record_event;
+use crate::model::backend::SqliteBackend;
+use crate::model::user_token::TokenScope;
+
+// The identity on whose behalf a backend method is called.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Caller {
+    // Trusted local process, e.g. the command line tool run by an operator.
+    System,
+    User(i64),
+    // A user acting through a token, limited to what its scope grants.
+    Token(i64, TokenScope),
+    Anonymous,
+}
+
+impl std::fmt::Display for Caller {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Caller::System => write!(f, "system"),
+            Caller::User(user_id) | Caller::Token(user_id, _) => write!(f, "user:{}", user_id),
+            Caller::Anonymous => write!(f, "anonymous"),
+        }
+    }
+}
+
+impl Caller {
+    pub fn user_id(&self) -> Option<i64> {
+        match self {
+            Caller::User(user_id) | Caller::Token(user_id, _) => Some(*user_id),
+            _ => None,
+        }
+    }
+
+    // Only a token may fall short of the required scope; every other
+    // caller is limited by its roles alone.
+    pub fn check_scope(&self, required: TokenScope) -> Result<(), PmrError> {
+        match self {
+            Caller::Token(_, scope) if !scope.grants(required) => Err(PmrError::PermissionDenied(
+                format!("token does not grant {:?} access", required))),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+pub trait UserBackend {
+    async fn add_user(&self, caller: &Caller, name: &str) -> Result<i64, PmrError>;
+    async fn list_users(&self) -> Result<Vec<UserRecord>, PmrError>;
+    async fn get_user_by_name(&self, name: &str) -> Result<UserRecord, PmrError>;
+}
+
+pub struct UserRecord {
+    pub id: i64,
+    pub name: String,
+    pub created: i64,
+}
+
+impl std::fmt::Display for UserRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {} - {}",
+            self.id,
+            &self.name,
+            Utc.timestamp(self.created, 0).to_rfc3339(),
+        )
+    }
+}
+
+#[async_trait]
+impl UserBackend for SqliteBackend {
+    async fn add_user(&self, caller: &Caller, name: &str) -> Result<i64, PmrError> {
+        if caller != &Caller::System {
+            return Err(PmrError::PermissionDenied("only the system may add users".to_string()));
+        }
+        let ts = Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
+
+        let id = sqlx::query!(
+            r#"
+INSERT INTO user ( name, created )
+VALUES ( ?1, ?2 )
+            "#,
+            name,
+            ts,
+        )
+        .execute(&mut tx)
+        .await?
+        .last_insert_rowid();
+
+        record_event(
+            &mut tx, caller, "user.add", None, &format!("user:{}", id), None, Some(json!({ "name": name })),
+        ).await?;
+
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    async fn list_users(&self) -> Result<Vec<UserRecord>, PmrError> {
+        let recs = sqlx::query_as!(UserRecord,
+            r#"
+SELECT id, name, created
+FROM user
+ORDER BY id
+            "#
+        )
+        .fetch_all(&*self.pool)
+        .await?;
+        Ok(recs)
+    }
+
+    async fn get_user_by_name(&self, name: &str) -> Result<UserRecord, PmrError> {
+        let rec = sqlx::query_as!(UserRecord,
+            r#"
+SELECT id, name, created
+FROM user
+WHERE name = ?1
+            "#,
+            name,
+        )
+        .fetch_one(&*self.pool)
+        .await?;
+        Ok(rec)
+    }
+}
@@ -0,0 +1,231 @@
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use rand::RngCore;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+use enum_primitive::FromPrimitive;
+
+use crate::error::PmrError;
+use crate::model::audit::record_event;
+use crate::model::backend::SqliteBackend;
+use crate::model::user::Caller;
+
+const TOKEN_PREFIX: &str = "pmr_";
+
+#[async_trait]
+pub trait UserTokenBackend {
+    // Returns the token itself, which is only ever available here as only
+    // its hash is stored.
+    async fn create_token(
+        &self, caller: &Caller, user_id: i64, scope: TokenScope, expires: Option<i64>, description: &str
+    ) -> Result<String, PmrError>;
+    async fn list_tokens(&self, caller: &Caller, user_id: i64) -> Result<Vec<UserTokenRecord>, PmrError>;
+    async fn revoke_token(&self, caller: &Caller, id: i64) -> Result<bool, PmrError>;
+    // Resolve a token presented by a client to the caller it acts as,
+    // provided that it is valid and its scope grants the required scope;
+    // the caller remains limited to the scope of the token.
+    async fn authenticate_token(&self, token: &str, required: TokenScope) -> Result<Caller, PmrError>;
+}
+
+enum_from_primitive! {
+// Ordered from least to most permissive; write implies read.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TokenScope {
+    Read,
+    Write,
+}
+}
+
+impl TokenScope {
+    pub fn grants(&self, required: TokenScope) -> bool {
+        (*self as i64) >= (required as i64)
+    }
+}
+
+impl std::str::FromStr for TokenScope {
+    type Err = PmrError;
+
+    fn from_str(s: &str) -> Result<Self, PmrError> {
+        match s {
+            "read" => Ok(TokenScope::Read),
+            "write" => Ok(TokenScope::Write),
+            _ => Err(PmrError::InvalidInput(format!("unknown scope '{}'", s))),
+        }
+    }
+}
+
+pub struct UserTokenRecord {
+    pub id: i64,
+    pub user_id: i64,
+    pub description: String,
+    pub scope: i64,
+    pub created: i64,
+    pub expires: Option<i64>,
+    pub revoked: Option<i64>,
+}
+
+impl std::fmt::Display for UserTokenRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {:?} - {} - {} - {}",
+            self.id,
+            TokenScope::from_i64(self.scope),
+            match self.expires {
+                Some(v) => Utc.timestamp_opt(v, 0).unwrap().to_rfc3339(),
+                None => "<never>".to_string(),
+            },
+            match self.revoked {
+                Some(v) => Utc.timestamp_opt(v, 0).unwrap().to_rfc3339(),
+                None => "<active>".to_string(),
+            },
+            &self.description,
+        )
+    }
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+// Users may manage their own tokens, the system may manage anyone's.
+fn check_token_owner(caller: &Caller, user_id: i64) -> Result<(), PmrError> {
+    match caller {
+        Caller::System => Ok(()),
+        Caller::User(id) | Caller::Token(id, _) if *id == user_id => Ok(()),
+        _ => Err(PmrError::PermissionDenied(format!("{:?} may not manage tokens of user {}", caller, user_id))),
+    }
+}
+
+#[async_trait]
+impl UserTokenBackend for SqliteBackend {
+    async fn create_token(
+        &self, caller: &Caller, user_id: i64, scope: TokenScope, expires: Option<i64>, description: &str
+    ) -> Result<String, PmrError> {
+        check_token_owner(caller, user_id)?;
+        // a read token must not be able to mint a write token
+        caller.check_scope(TokenScope::Write)?;
+        let ts = Utc::now().timestamp();
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let token = format!("{}{}", TOKEN_PREFIX, hex::encode(secret));
+        let token_hash = hash_token(&token);
+        let scope_ = scope as i32;
+        let mut tx = self.pool.begin().await?;
+
+        let id = sqlx::query!(
+            r#"
+INSERT INTO user_token ( user_id, token_hash, description, scope, created, expires )
+VALUES ( ?1, ?2, ?3, ?4, ?5, ?6 )
+            "#,
+            user_id,
+            token_hash,
+            description,
+            scope_,
+            ts,
+            expires,
+        )
+        .execute(&mut tx)
+        .await?
+        .last_insert_rowid();
+
+        // neither the token nor its hash belong in the log
+        record_event(
+            &mut tx, caller, "user_token.create", None, &format!("user_token:{}", id), None,
+            Some(json!({ "user_id": user_id, "scope": scope_, "expires": expires, "description": description })),
+        ).await?;
+
+        tx.commit().await?;
+        Ok(token)
+    }
+
+    async fn list_tokens(&self, caller: &Caller, user_id: i64) -> Result<Vec<UserTokenRecord>, PmrError> {
+        check_token_owner(caller, user_id)?;
+        let recs = sqlx::query_as!(UserTokenRecord,
+            r#"
+SELECT id, user_id, description, scope, created, expires, revoked
+FROM user_token
+WHERE user_id = ?1
+ORDER BY id
+            "#,
+            user_id,
+        )
+        .fetch_all(&*self.pool)
+        .await?;
+        Ok(recs)
+    }
+
+    async fn revoke_token(&self, caller: &Caller, id: i64) -> Result<bool, PmrError> {
+        caller.check_scope(TokenScope::Write)?;
+        let rec = sqlx::query!(
+            r#"
+SELECT user_id
+FROM user_token
+WHERE id = ?1
+            "#,
+            id,
+        )
+        .fetch_optional(&*self.pool)
+        .await?;
+        match rec {
+            Some(rec) => check_token_owner(caller, rec.user_id)?,
+            None => return Ok(false),
+        };
+
+        let ts = Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
+        let rows_affected = sqlx::query!(
+            r#"
+UPDATE user_token
+SET revoked = ?1
+WHERE id = ?2 AND revoked IS NULL
+            "#,
+            ts,
+            id,
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+
+        if rows_affected > 0 {
+            record_event(
+                &mut tx, caller, "user_token.revoke", None, &format!("user_token:{}", id),
+                Some(json!({ "revoked": null })), Some(json!({ "revoked": ts })),
+            ).await?;
+        }
+
+        tx.commit().await?;
+        Ok(rows_affected > 0)
+    }
+
+    async fn authenticate_token(&self, token: &str, required: TokenScope) -> Result<Caller, PmrError> {
+        if !token.starts_with(TOKEN_PREFIX) {
+            return Err(PmrError::PermissionDenied("invalid token".to_string()));
+        }
+        let ts = Utc::now().timestamp();
+        let token_hash = hash_token(token);
+        let rec = sqlx::query!(
+            r#"
+SELECT user_id, scope
+FROM user_token
+WHERE token_hash = ?1
+    AND revoked IS NULL
+    AND (expires IS NULL OR expires > ?2)
+            "#,
+            token_hash,
+            ts,
+        )
+        .fetch_optional(&*self.pool)
+        .await?;
+        match rec {
+            Some(rec) => match TokenScope::from_i64(rec.scope) {
+                Some(scope) if scope.grants(required) => Ok(Caller::Token(rec.user_id, scope)),
+                _ => Err(PmrError::PermissionDenied(format!("token does not grant {:?} access", required))),
+            },
+            // expired, revoked and unknown tokens are not told apart
+            None => Err(PmrError::PermissionDenied("invalid token".to_string())),
+        }
+    }
+}
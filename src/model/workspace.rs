@@ -1,25 +1,96 @@
 use async_trait::async_trait;
 use chrono::Utc;
+use serde_json::json;
 use std::fmt;
 
+use enum_primitive::FromPrimitive;
+
+use crate::model::audit::record_event;
+use crate::error::PmrError;
 use crate::model::backend::SqliteBackend;
+use crate::model::user::Caller;
+use crate::model::user_token::TokenScope;
+use crate::model::workspace_acl::{
+    WorkspaceAclBackend,
+    WorkspaceRole,
+};
+use crate::model::workspace_import::link_workspace_imports;
+use crate::model::workspace_keyword::normalize_keyword;
+use crate::model::workspace_url::{
+    WorkspaceUrlError,
+    check_workspace_url,
+    url_key,
+};
 
 #[async_trait]
 pub trait WorkspaceBackend {
     async fn add_workspace(
-        &self, url: &str, description: &str, long_description: &str
-    ) -> anyhow::Result<i64>;
+        &self, caller: &Caller, url: &str, description: &str, long_description: &str
+    ) -> Result<i64, PmrError>;
     async fn update_workspace(
-        &self, id: i64, description: &str, long_description: &str
-    ) -> anyhow::Result<bool>;
-    async fn list_workspaces(&self) -> anyhow::Result<Vec<WorkspaceRecord>>;
-    async fn get_workspace_by_id(&self, id: i64) -> anyhow::Result<WorkspaceRecord>;
+        &self, caller: &Caller, id: i64, description: &str, long_description: &str
+    ) -> Result<bool, PmrError>;
+    async fn set_workspace_visibility(
+        &self, caller: &Caller, id: i64, visibility: WorkspaceVisibility, embargo_until: Option<i64>
+    ) -> Result<bool, PmrError>;
+    async fn set_workspace_archived(&self, caller: &Caller, id: i64, archived: bool) -> Result<bool, PmrError>;
+    // Removes the workspace and every row referring to it, but not its
+    // repo (see delete_workspace_and_repo); with dry_run the changes are
+    // counted and then rolled back.
+    async fn delete_workspace(
+        &self, caller: &Caller, id: i64, dry_run: bool
+    ) -> Result<Option<WorkspaceDeleteSummary>, PmrError>;
+    async fn list_workspaces(&self, caller: &Caller) -> Result<Vec<WorkspaceRecord>, PmrError>;
+    // One page of the workspaces visible to the caller that match the
+    // query, along with how many match in all.
+    async fn query_workspaces(&self, caller: &Caller, query: &WorkspaceQuery) -> Result<WorkspacePage, PmrError>;
+    async fn get_workspace_by_id(&self, caller: &Caller, id: i64) -> Result<WorkspaceRecord, PmrError>;
+    async fn is_workspace_visible(&self, caller: &Caller, id: i64) -> Result<bool, PmrError>;
+    // Regardless of visibility, as a url may only be registered once; any
+    // spelling of the url with the same key will match.
+    async fn get_workspace_id_by_url(&self, url: &str) -> Result<Option<i64>, PmrError>;
+    // Fill in the key of a workspace registered before keys were kept.
+    async fn update_workspace_url_key(&self, caller: &Caller, id: i64) -> Result<String, PmrError>;
+}
+
+enum_from_primitive! {
+// An embargoed workspace becomes public once embargo_until has passed.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WorkspaceVisibility {
+    Public,
+    Private,
+    Embargoed,
+}
+}
+
+impl std::str::FromStr for WorkspaceVisibility {
+    type Err = PmrError;
+
+    fn from_str(s: &str) -> Result<Self, PmrError> {
+        match s {
+            "public" => Ok(WorkspaceVisibility::Public),
+            "private" => Ok(WorkspaceVisibility::Private),
+            "embargoed" => Ok(WorkspaceVisibility::Embargoed),
+            _ => Err(PmrError::InvalidInput(format!("unknown visibility '{}'", s))),
+        }
+    }
 }
 
+#[derive(Clone)]
 pub struct WorkspaceRecord {
     pub id: i64,
     pub url: String,
+    pub url_key: Option<String>,
     pub description: Option<String>,
+    pub visibility: i64,
+    pub embargo_until: Option<i64>,
+    pub archived: Option<i64>,
+}
+
+impl WorkspaceRecord {
+    pub fn visibility(&self) -> Option<WorkspaceVisibility> {
+        WorkspaceVisibility::from_i64(self.visibility)
+    }
 }
 
 impl std::fmt::Display for WorkspaceRecord {
@@ -37,30 +108,189 @@ impl std::fmt::Display for WorkspaceRecord {
     }
 }
 
+// The values are what the listing query sorts on, so must not change.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WorkspaceSort {
+    Id = 0,
+    Created = 1,
+    Url = 2,
+}
+
+impl std::str::FromStr for WorkspaceSort {
+    type Err = PmrError;
+
+    fn from_str(s: &str) -> Result<Self, PmrError> {
+        match s {
+            "id" => Ok(WorkspaceSort::Id),
+            "created" => Ok(WorkspaceSort::Created),
+            "url" => Ok(WorkspaceSort::Url),
+            _ => Err(PmrError::InvalidInput(format!("unknown sort order '{}'", s))),
+        }
+    }
+}
+
+// The text matches anywhere in the url or description, and the created
+// range includes created_from but not created_before, as with the audit
+// log; no limit returns every match.
+#[derive(Debug, Clone)]
+pub struct WorkspaceQuery {
+    pub text: Option<String>,
+    pub keyword: Option<String>,
+    pub created_from: Option<i64>,
+    pub created_before: Option<i64>,
+    pub sort: WorkspaceSort,
+    pub descending: bool,
+    pub limit: Option<i64>,
+    pub offset: i64,
+}
+
+impl Default for WorkspaceQuery {
+    fn default() -> Self {
+        WorkspaceQuery {
+            text: None,
+            keyword: None,
+            created_from: None,
+            created_before: None,
+            sort: WorkspaceSort::Id,
+            descending: false,
+            limit: None,
+            offset: 0,
+        }
+    }
+}
+
+pub struct WorkspacePage {
+    pub total: i64,
+    pub workspaces: Vec<WorkspaceRecord>,
+}
+
+// Rows removed (or that would be removed) by a delete; importers are the
+// imports elsewhere that pointed at the workspace and were unlinked.
+#[derive(Debug, Default)]
+pub struct WorkspaceDeleteSummary {
+    pub sync: u64,
+    pub tags: u64,
+    pub acl: u64,
+    pub metadata: u64,
+    pub imports: u64,
+    pub importers: u64,
+    pub blobs: u64,
+    pub keywords: u64,
+    pub maintenance: u64,
+}
+
+impl std::fmt::Display for WorkspaceDeleteSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} sync - {} tags - {} acl - {} metadata - {} imports - {} importers - {} blobs - {} keywords - {} maintenance",
+            self.sync,
+            self.tags,
+            self.acl,
+            self.metadata,
+            self.imports,
+            self.importers,
+            self.blobs,
+            self.keywords,
+            self.maintenance,
+        )
+    }
+}
+
 #[async_trait]
 impl WorkspaceBackend for SqliteBackend {
-    async fn add_workspace(&self, url: &str, description: &str, long_description: &str) -> anyhow::Result<i64> {
+    async fn add_workspace(&self, caller: &Caller, url: &str, description: &str, long_description: &str) -> Result<i64, PmrError> {
+        if caller == &Caller::Anonymous {
+            return Err(PmrError::PermissionDenied("anonymous callers may not add workspaces".to_string()));
+        }
+        caller.check_scope(TokenScope::Write)?;
+        let url = url.trim();
+        let url_key = check_workspace_url(caller, url)?;
         let ts = Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
+
+        let existing = sqlx::query!(
+            r#"
+SELECT id
+FROM workspace
+WHERE url_key = ?1
+            "#,
+            url_key,
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+        if let Some(rec) = existing {
+            return Err(WorkspaceUrlError::Conflict(rec.id).into());
+        }
 
         let id = sqlx::query!(
             r#"
-INSERT INTO workspace ( url, description, long_description, created )
-VALUES ( ?1, ?2, ?3, ?4 )
+INSERT INTO workspace ( url, url_key, description, long_description, created )
+VALUES ( ?1, ?2, ?3, ?4, ?5 )
             "#,
             url,
+            url_key,
             description,
             long_description,
             ts,
         )
-        .execute(&*self.pool)
+        .execute(&mut tx)
         .await?
         .last_insert_rowid();
+        link_workspace_imports(&mut tx, id, url, &url_key).await?;
+
+        // the user registering the workspace becomes its owner
+        if let Some(user_id) = caller.user_id() {
+            let role_id = WorkspaceRole::Owner as i32;
+            sqlx::query!(
+                r#"
+INSERT INTO workspace_acl ( workspace_id, user_id, role_id )
+VALUES ( ?1, ?2, ?3 )
+                "#,
+                id,
+                user_id,
+                role_id,
+            )
+            .execute(&mut tx)
+            .await?;
+        }
 
+        record_event(
+            &mut tx, caller, "workspace.add", Some(id), &format!("workspace:{}", id), None,
+            Some(json!({
+                "url": url,
+                "description": description,
+                "long_description": long_description,
+            })),
+        ).await?;
+
+        tx.commit().await?;
         Ok(id)
     }
 
-    async fn update_workspace(&self, id: i64, description: &str, long_description: &str) -> anyhow::Result<bool> {
-        let rows_affected = sqlx::query!(
+    async fn update_workspace(&self, caller: &Caller, id: i64, description: &str, long_description: &str) -> Result<bool, PmrError> {
+        WorkspaceAclBackend::check_workspace_permission(self, caller, id, WorkspaceRole::Editor).await?;
+        let mut tx = self.pool.begin().await?;
+
+        let before = sqlx::query!(
+            r#"
+SELECT description, long_description
+FROM workspace
+WHERE id = ?1
+            "#,
+            id,
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+        let before = match before {
+            Some(rec) => json!({
+                "description": rec.description,
+                "long_description": rec.long_description,
+            }),
+            None => return Ok(false),
+        };
+
+        sqlx::query!(
             r#"
 UPDATE workspace
 SET description = ?1, long_description = ?2
@@ -70,38 +300,506 @@ WHERE id = ?3
             long_description,
             id,
         )
-        .execute(&*self.pool)
+        .execute(&mut tx)
+        .await?;
+
+        record_event(
+            &mut tx, caller, "workspace.update", Some(id), &format!("workspace:{}", id), Some(before),
+            Some(json!({
+                "description": description,
+                "long_description": long_description,
+            })),
+        ).await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn set_workspace_visibility(
+        &self, caller: &Caller, id: i64, visibility: WorkspaceVisibility, embargo_until: Option<i64>
+    ) -> Result<bool, PmrError> {
+        WorkspaceAclBackend::check_workspace_permission(self, caller, id, WorkspaceRole::Owner).await?;
+        if visibility == WorkspaceVisibility::Embargoed && embargo_until.is_none() {
+            return Err(PmrError::InvalidInput("an embargoed workspace requires an end date for the embargo".to_string()));
+        }
+        let visibility_ = visibility as i32;
+        let mut tx = self.pool.begin().await?;
+
+        let before = sqlx::query!(
+            r#"
+SELECT visibility, embargo_until
+FROM workspace
+WHERE id = ?1
+            "#,
+            id,
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+        let before = match before {
+            Some(rec) => json!({
+                "visibility": rec.visibility,
+                "embargo_until": rec.embargo_until,
+            }),
+            None => return Ok(false),
+        };
+
+        sqlx::query!(
+            r#"
+UPDATE workspace
+SET visibility = ?1, embargo_until = ?2
+WHERE id = ?3
+            "#,
+            visibility_,
+            embargo_until,
+            id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        record_event(
+            &mut tx, caller, "workspace.visibility", Some(id), &format!("workspace:{}", id), Some(before),
+            Some(json!({
+                "visibility": visibility_,
+                "embargo_until": embargo_until,
+            })),
+        ).await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn set_workspace_archived(&self, caller: &Caller, id: i64, archived: bool) -> Result<bool, PmrError> {
+        WorkspaceAclBackend::check_workspace_permission(self, caller, id, WorkspaceRole::Owner).await?;
+        let ts = if archived { Some(Utc::now().timestamp()) } else { None };
+        let mut tx = self.pool.begin().await?;
+
+        let before = sqlx::query!(
+            r#"
+SELECT archived
+FROM workspace
+WHERE id = ?1
+            "#,
+            id,
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+        let before = match before {
+            Some(rec) => json!({ "archived": rec.archived }),
+            None => return Ok(false),
+        };
+
+        sqlx::query!(
+            r#"
+UPDATE workspace
+SET archived = ?1
+WHERE id = ?2
+            "#,
+            ts,
+            id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        record_event(
+            &mut tx, caller, if archived { "workspace.archive" } else { "workspace.unarchive" },
+            Some(id), &format!("workspace:{}", id), Some(before), Some(json!({ "archived": ts })),
+        ).await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn delete_workspace(
+        &self, caller: &Caller, id: i64, dry_run: bool
+    ) -> Result<Option<WorkspaceDeleteSummary>, PmrError> {
+        // unlike archiving this cannot be undone, so it is left to operators
+        if caller != &Caller::System {
+            return Err(PmrError::PermissionDenied(format!("{:?} may not delete workspaces", caller)));
+        }
+        let mut tx = self.pool.begin().await?;
+
+        let before = sqlx::query!(
+            r#"
+SELECT url, description, long_description, created
+FROM workspace
+WHERE id = ?1
+            "#,
+            id,
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+        let before = match before {
+            Some(rec) => json!({
+                "url": rec.url,
+                "description": rec.description,
+                "long_description": rec.long_description,
+                "created": rec.created,
+            }),
+            None => return Ok(None),
+        };
+
+        let mut summary = WorkspaceDeleteSummary::default();
+        summary.sync = sqlx::query!(
+            r#"
+DELETE FROM workspace_sync
+WHERE workspace_id = ?1
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        summary.tags = sqlx::query!(
+            r#"
+DELETE FROM workspace_tag
+WHERE workspace_id = ?1
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        summary.acl = sqlx::query!(
+            r#"
+DELETE FROM workspace_acl
+WHERE workspace_id = ?1
+            "#,
+            id,
+        )
+        .execute(&mut tx)
         .await?
         .rows_affected();
+        summary.metadata = sqlx::query!(
+            r#"
+DELETE FROM workspace_metadata
+WHERE workspace_id = ?1
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        summary.imports = sqlx::query!(
+            r#"
+DELETE FROM workspace_import
+WHERE workspace_id = ?1
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        // imports from other workspaces revert to being unregistered
+        summary.importers = sqlx::query!(
+            r#"
+UPDATE workspace_import
+SET target_workspace_id = NULL
+WHERE target_workspace_id = ?1
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        summary.blobs = sqlx::query!(
+            r#"
+DELETE FROM workspace_blob_fts
+WHERE workspace_id = ?1
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        summary.keywords = sqlx::query!(
+            r#"
+DELETE FROM workspace_keyword
+WHERE workspace_id = ?1
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        summary.maintenance = sqlx::query!(
+            r#"
+DELETE FROM workspace_maintenance
+WHERE workspace_id = ?1
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+        sqlx::query!(
+            r#"
+UPDATE workspace
+SET superceded_by_id = NULL
+WHERE superceded_by_id = ?1
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?;
+        // workspace_fts is kept in step by its trigger
+        sqlx::query!(
+            r#"
+DELETE FROM workspace
+WHERE id = ?1
+            "#,
+            id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        if dry_run {
+            tx.rollback().await?;
+            return Ok(Some(summary));
+        }
+
+        record_event(
+            &mut tx, caller, "workspace.delete", Some(id), &format!("workspace:{}", id), Some(before), None,
+        ).await?;
 
-        Ok(rows_affected > 0)
+        tx.commit().await?;
+        Ok(Some(summary))
     }
 
-    async fn list_workspaces(&self) -> anyhow::Result<Vec<WorkspaceRecord>> {
+    async fn list_workspaces(&self, caller: &Caller) -> Result<Vec<WorkspaceRecord>, PmrError> {
+        // every visible workspace by id, as a single unlimited page
+        Ok(self.query_workspaces(caller, &WorkspaceQuery::default()).await?.workspaces)
+    }
+
+    async fn query_workspaces(&self, caller: &Caller, query: &WorkspaceQuery) -> Result<WorkspacePage, PmrError> {
+        let ts = Utc::now().timestamp();
+        let is_system = caller == &Caller::System;
+        let user_id = caller.user_id();
+        // the text is matched literally, so LIKE wildcards in it are escaped
+        let pattern = query.text.as_ref().map(|text| format!(
+            "%{}%", text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")));
+        let keyword = query.keyword.as_deref().map(normalize_keyword);
+        let sort = query.sort as i64;
+        // sqlite treats a negative limit as no limit
+        let limit = query.limit.unwrap_or(-1);
+        let total = sqlx::query!(
+            r#"
+SELECT COUNT(*) AS total
+FROM workspace
+WHERE (?1 OR (archived IS NULL AND workspace.id IN (
+    SELECT workspace_id FROM workspace_reader
+    WHERE since <= ?2 AND (user_id IS NULL OR user_id = ?3)
+)))
+AND (?4 IS NULL OR url LIKE ?4 ESCAPE '\' OR description LIKE ?4 ESCAPE '\')
+AND (?5 IS NULL OR created >= ?5)
+AND (?6 IS NULL OR created < ?6)
+AND (?7 IS NULL OR EXISTS (
+    SELECT 1 FROM workspace_keyword
+    JOIN keyword ON keyword.id = workspace_keyword.keyword_id
+    WHERE workspace_keyword.workspace_id = workspace.id AND keyword.name = ?7
+))
+            "#,
+            is_system,
+            ts,
+            user_id,
+            pattern,
+            query.created_from,
+            query.created_before,
+            keyword,
+        )
+        .fetch_one(&*self.pool)
+        .await?
+        .total;
+        // only one of the two sort keys is ever not null
         let recs = sqlx::query_as!(WorkspaceRecord,
             r#"
-SELECT id, url, description
+SELECT id, url, url_key, description, visibility, embargo_until, archived
 FROM workspace
-ORDER BY id
-            "#
+WHERE (?1 OR (archived IS NULL AND workspace.id IN (
+    SELECT workspace_id FROM workspace_reader
+    WHERE since <= ?2 AND (user_id IS NULL OR user_id = ?3)
+)))
+AND (?4 IS NULL OR url LIKE ?4 ESCAPE '\' OR description LIKE ?4 ESCAPE '\')
+AND (?5 IS NULL OR created >= ?5)
+AND (?6 IS NULL OR created < ?6)
+AND (?7 IS NULL OR EXISTS (
+    SELECT 1 FROM workspace_keyword
+    JOIN keyword ON keyword.id = workspace_keyword.keyword_id
+    WHERE workspace_keyword.workspace_id = workspace.id AND keyword.name = ?7
+))
+ORDER BY
+    CASE WHEN NOT ?8 THEN (CASE ?9 WHEN 1 THEN created WHEN 2 THEN url ELSE id END) END ASC,
+    CASE WHEN ?8 THEN (CASE ?9 WHEN 1 THEN created WHEN 2 THEN url ELSE id END) END DESC,
+    id
+LIMIT ?10 OFFSET ?11
+            "#,
+            is_system,
+            ts,
+            user_id,
+            pattern,
+            query.created_from,
+            query.created_before,
+            keyword,
+            query.descending,
+            sort,
+            limit,
+            query.offset,
         )
         .fetch_all(&*self.pool)
         .await?;
-        Ok(recs)
+        Ok(WorkspacePage {
+            total: total as i64,
+            workspaces: recs,
+        })
     }
 
-    async fn get_workspace_by_id(&self, id: i64) -> anyhow::Result<WorkspaceRecord> {
+    async fn get_workspace_by_id(&self, caller: &Caller, id: i64) -> Result<WorkspaceRecord, PmrError> {
         // ignoring superceded_by_id for now?
+        let ts = Utc::now().timestamp();
+        let is_system = caller == &Caller::System;
+        let user_id = caller.user_id();
+        // workspaces hidden from the caller are indistinguishable from
+        // those that don't exist
         let rec = sqlx::query_as!(WorkspaceRecord,
             r#"
-SELECT id, url, description
+SELECT id, url, url_key, description, visibility, embargo_until, archived
+FROM workspace
+WHERE id = ?1 AND (?2 OR workspace.id IN (
+    SELECT workspace_id FROM workspace_reader
+    WHERE since <= ?3 AND (user_id IS NULL OR user_id = ?4)
+))
+            "#,
+            id,
+            is_system,
+            ts,
+            user_id,
+        )
+        .fetch_optional(&*self.pool)
+        .await?;
+        rec.ok_or_else(|| PmrError::NotFound(format!("workspace {}", id)))
+    }
+
+    async fn is_workspace_visible(&self, caller: &Caller, id: i64) -> Result<bool, PmrError> {
+        match self.get_workspace_by_id(caller, id).await {
+            Ok(_) => Ok(true),
+            Err(PmrError::NotFound(_)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_workspace_id_by_url(&self, url: &str) -> Result<Option<i64>, PmrError> {
+        let url = url.trim();
+        let url_key = url_key(url)?;
+        let rec = sqlx::query!(
+            r#"
+SELECT id
+FROM workspace
+WHERE url_key = ?1 OR url = ?2
+            "#,
+            url_key,
+            url,
+        )
+        .fetch_optional(&*self.pool)
+        .await?;
+        Ok(rec.map(|rec| rec.id))
+    }
+
+    async fn update_workspace_url_key(&self, caller: &Caller, id: i64) -> Result<String, PmrError> {
+        WorkspaceAclBackend::check_workspace_permission(self, caller, id, WorkspaceRole::Owner).await?;
+        let mut tx = self.pool.begin().await?;
+
+        let rec = sqlx::query!(
+            r#"
+SELECT url, url_key
 FROM workspace
 WHERE id = ?1
             "#,
             id,
         )
-        .fetch_one(&*self.pool)
+        .fetch_one(&mut tx)
+        .await?;
+        let url_key = url_key(&rec.url)?;
+        let existing = sqlx::query!(
+            r#"
+SELECT id
+FROM workspace
+WHERE url_key = ?1 AND id != ?2
+            "#,
+            url_key,
+            id,
+        )
+        .fetch_optional(&mut tx)
+        .await?;
+        if let Some(existing) = existing {
+            return Err(WorkspaceUrlError::Conflict(existing.id).into());
+        }
+
+        sqlx::query!(
+            r#"
+UPDATE workspace
+SET url_key = ?1
+WHERE id = ?2
+            "#,
+            url_key,
+            id,
+        )
+        .execute(&mut tx)
         .await?;
-        Ok(rec)
+        link_workspace_imports(&mut tx, id, &rec.url, &url_key).await?;
+
+        record_event(
+            &mut tx, caller, "workspace.url_key", Some(id), &format!("workspace:{}", id),
+            Some(json!({ "url_key": rec.url_key })), Some(json!({ "url_key": url_key })),
+        ).await?;
+
+        tx.commit().await?;
+        Ok(url_key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::workspace_maintenance::WorkspaceMaintenanceBackend;
+    use crate::utils::test_backend;
+
+    #[async_std::test]
+    async fn test_delete_maintained_workspace() {
+        let backend = test_backend().await;
+        let id = WorkspaceBackend::add_workspace(
+            &backend, &Caller::System, "https://example.com/repo", "", "").await.unwrap();
+        WorkspaceMaintenanceBackend::record_maintenance(&backend, &Caller::System, id, 0, 200, 100).await.unwrap();
+
+        let summary = backend.delete_workspace(&Caller::System, id, true).await.unwrap().unwrap();
+        assert_eq!(summary.maintenance, 1);
+        assert!(backend.get_workspace_by_id(&Caller::System, id).await.is_ok());
+
+        let summary = backend.delete_workspace(&Caller::System, id, false).await.unwrap().unwrap();
+        assert_eq!(summary.maintenance, 1);
+        assert!(matches!(backend.get_workspace_by_id(&Caller::System, id).await, Err(PmrError::NotFound(_))));
+        assert!(backend.delete_workspace(&Caller::System, id, false).await.unwrap().is_none());
+    }
+    #[async_std::test]
+    async fn test_query_workspaces_created() {
+        let backend = test_backend().await;
+        let mut ids = Vec::new();
+        for (i, created) in [100, 200, 300].iter().enumerate() {
+            let id = WorkspaceBackend::add_workspace(
+                &backend, &Caller::System, &format!("https://example.com/{}", i), "", "").await.unwrap();
+            sqlx::query("UPDATE workspace SET created = ?1 WHERE id = ?2")
+                .bind(created)
+                .bind(id)
+                .execute(&*backend.pool)
+                .await
+                .unwrap();
+            ids.push(id);
+        }
+        let query = WorkspaceQuery {
+            created_from: Some(100),
+            created_before: Some(300),
+            ..Default::default()
+        };
+        let page = backend.query_workspaces(&Caller::System, &query).await.unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.workspaces.iter().map(|rec| rec.id).collect::<Vec<_>>(), ids[..2]);
     }
 }
@@ -0,0 +1,231 @@
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use sqlx::{Sqlite, Transaction};
+use std::fmt;
+
+use enum_primitive::FromPrimitive;
+
+use crate::model::audit::record_event;
+use crate::error::PmrError;
+use crate::model::backend::SqliteBackend;
+use crate::model::user::Caller;
+use crate::model::user_token::TokenScope;
+use crate::model::workspace::WorkspaceBackend;
+
+#[async_trait]
+pub trait WorkspaceAclBackend {
+    async fn get_workspace_role(&self, caller: &Caller, workspace_id: i64) -> Result<Option<WorkspaceRole>, PmrError>;
+    async fn check_workspace_permission(
+        &self, caller: &Caller, workspace_id: i64, required: WorkspaceRole
+    ) -> Result<(), PmrError>;
+    async fn grant_workspace_role(
+        &self, caller: &Caller, workspace_id: i64, user_id: i64, role: WorkspaceRole
+    ) -> Result<(), PmrError>;
+    async fn revoke_workspace_role(&self, caller: &Caller, workspace_id: i64, user_id: i64) -> Result<bool, PmrError>;
+    async fn get_workspace_acl(&self, caller: &Caller, workspace_id: i64) -> Result<Vec<WorkspaceAclRecord>, PmrError>;
+}
+
+enum_from_primitive! {
+// Ordered from most to least privileged, matching the role table.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum WorkspaceRole {
+    Owner,
+    Editor,
+    Viewer,
+}
+}
+
+impl WorkspaceRole {
+    // Whether this role allows what the required role allows.
+    pub fn grants(&self, required: WorkspaceRole) -> bool {
+        (*self as i64) <= (required as i64)
+    }
+
+    // The token scope needed to act in this role; only viewing is a read.
+    pub fn scope(&self) -> TokenScope {
+        match self {
+            WorkspaceRole::Viewer => TokenScope::Read,
+            _ => TokenScope::Write,
+        }
+    }
+}
+
+impl std::str::FromStr for WorkspaceRole {
+    type Err = PmrError;
+
+    fn from_str(s: &str) -> Result<Self, PmrError> {
+        match s {
+            "owner" => Ok(WorkspaceRole::Owner),
+            "editor" => Ok(WorkspaceRole::Editor),
+            "viewer" => Ok(WorkspaceRole::Viewer),
+            _ => Err(PmrError::InvalidInput(format!("unknown role '{}'", s))),
+        }
+    }
+}
+
+pub struct WorkspaceAclRecord {
+    pub workspace_id: i64,
+    pub user_id: i64,
+    pub user_name: String,
+    pub role_id: i64,
+}
+
+impl std::fmt::Display for WorkspaceAclRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {} - {:?}",
+            self.user_id,
+            &self.user_name,
+            WorkspaceRole::from_i64(self.role_id),
+        )
+    }
+}
+
+async fn current_role(
+    tx: &mut Transaction<'_, Sqlite>, workspace_id: i64, user_id: i64
+) -> Result<Option<Value>, PmrError> {
+    let rec = sqlx::query!(
+        r#"
+SELECT role_id
+FROM workspace_acl
+WHERE workspace_id = ?1 AND user_id = ?2
+        "#,
+        workspace_id,
+        user_id,
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    Ok(rec.map(|rec| json!({ "user_id": user_id, "role_id": rec.role_id })))
+}
+
+#[async_trait]
+impl WorkspaceAclBackend for SqliteBackend {
+    async fn get_workspace_role(&self, caller: &Caller, workspace_id: i64) -> Result<Option<WorkspaceRole>, PmrError> {
+        let user_id = match caller {
+            Caller::System => return Ok(Some(WorkspaceRole::Owner)),
+            Caller::Anonymous => return Ok(None),
+            Caller::User(user_id) | Caller::Token(user_id, _) => user_id,
+        };
+        let rec = sqlx::query!(
+            r#"
+SELECT role_id
+FROM workspace_acl
+WHERE workspace_id = ?1 AND user_id = ?2
+            "#,
+            workspace_id,
+            user_id,
+        )
+        .fetch_optional(&*self.pool)
+        .await?;
+        Ok(rec.and_then(|rec| WorkspaceRole::from_i64(rec.role_id)))
+    }
+
+    async fn check_workspace_permission(
+        &self, caller: &Caller, workspace_id: i64, required: WorkspaceRole
+    ) -> Result<(), PmrError> {
+        caller.check_scope(required.scope())?;
+        match self.get_workspace_role(caller, workspace_id).await? {
+            Some(role) if role.grants(required) => Ok(()),
+            _ => Err(PmrError::PermissionDenied(
+                format!("{:?} is not {:?} of workspace {}", caller, required, workspace_id))),
+        }
+    }
+
+    async fn grant_workspace_role(
+        &self, caller: &Caller, workspace_id: i64, user_id: i64, role: WorkspaceRole
+    ) -> Result<(), PmrError> {
+        self.check_workspace_permission(caller, workspace_id, WorkspaceRole::Owner).await?;
+        let role_id = role as i32;
+        let mut tx = self.pool.begin().await?;
+        let before = current_role(&mut tx, workspace_id, user_id).await?;
+        sqlx::query!(
+            r#"
+INSERT INTO workspace_acl ( workspace_id, user_id, role_id )
+VALUES ( ?1, ?2, ?3 )
+ON CONFLICT (workspace_id, user_id) DO UPDATE SET role_id = excluded.role_id
+            "#,
+            workspace_id,
+            user_id,
+            role_id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        record_event(
+            &mut tx, caller, "workspace_acl.grant", Some(workspace_id), &format!("workspace:{}", workspace_id),
+            before, Some(json!({ "user_id": user_id, "role_id": role_id })),
+        ).await?;
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn revoke_workspace_role(&self, caller: &Caller, workspace_id: i64, user_id: i64) -> Result<bool, PmrError> {
+        self.check_workspace_permission(caller, workspace_id, WorkspaceRole::Owner).await?;
+        let mut tx = self.pool.begin().await?;
+        let before = match current_role(&mut tx, workspace_id, user_id).await? {
+            Some(before) => before,
+            None => return Ok(false),
+        };
+        sqlx::query!(
+            r#"
+DELETE FROM workspace_acl
+WHERE workspace_id = ?1 AND user_id = ?2
+            "#,
+            workspace_id,
+            user_id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        record_event(
+            &mut tx, caller, "workspace_acl.revoke", Some(workspace_id), &format!("workspace:{}", workspace_id),
+            Some(before), None,
+        ).await?;
+
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn get_workspace_acl(&self, caller: &Caller, workspace_id: i64) -> Result<Vec<WorkspaceAclRecord>, PmrError> {
+        if !WorkspaceBackend::is_workspace_visible(self, caller, workspace_id).await? {
+            return Err(PmrError::NotFound(format!("workspace {}", workspace_id)));
+        }
+        let recs = sqlx::query_as!(WorkspaceAclRecord,
+            r#"
+SELECT workspace_acl.workspace_id, workspace_acl.user_id, user.name AS user_name, workspace_acl.role_id
+FROM workspace_acl
+JOIN user ON user.id = workspace_acl.user_id
+WHERE workspace_acl.workspace_id = ?1
+ORDER BY workspace_acl.role_id, workspace_acl.user_id
+            "#,
+            workspace_id,
+        )
+        .fetch_all(&*self.pool)
+        .await?;
+        Ok(recs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grants() {
+        use WorkspaceRole::*;
+        assert!(Owner.grants(Owner) && Owner.grants(Editor) && Owner.grants(Viewer));
+        assert!(!Editor.grants(Owner) && Editor.grants(Editor) && Editor.grants(Viewer));
+        assert!(!Viewer.grants(Owner) && !Viewer.grants(Editor) && Viewer.grants(Viewer));
+    }
+
+    #[test]
+    fn test_role_from_i64() {
+        // the order must match the ids in the role table
+        assert_eq!(WorkspaceRole::from_i64(0), Some(WorkspaceRole::Owner));
+        assert_eq!(WorkspaceRole::from_i64(1), Some(WorkspaceRole::Editor));
+        assert_eq!(WorkspaceRole::from_i64(2), Some(WorkspaceRole::Viewer));
+        assert_eq!(WorkspaceRole::from_i64(3), None);
+    }
+}
@@ -0,0 +1,265 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_json::json;
+use sqlx::{Sqlite, Transaction};
+use std::fmt;
+
+use crate::error::PmrError;
+use crate::model::audit::record_event;
+use crate::model::backend::SqliteBackend;
+use crate::model::user::Caller;
+use crate::model::workspace::WorkspaceBackend;
+use crate::model::workspace_acl::{
+    WorkspaceAclBackend,
+    WorkspaceRole,
+};
+use crate::model::workspace_url::url_key;
+
+#[async_trait]
+pub trait WorkspaceImportBackend {
+    async fn index_workspace_imports(
+        &self, caller: &Caller, workspace_id: i64, commit_id: &str, imports: &[WorkspaceImport]
+    ) -> Result<u64, PmrError>;
+    // what the model(s) at the commit depend on
+    async fn get_workspace_imports(
+        &self, caller: &Caller, workspace_id: i64, commit_id: &str, path: Option<&str>
+    ) -> Result<Vec<WorkspaceImportRecord>, PmrError>;
+    // what imports the model(s) in the workspace, from the workspaces
+    // visible to the caller
+    async fn get_workspace_importers(
+        &self, caller: &Caller, workspace_id: i64, path: Option<&str>
+    ) -> Result<Vec<WorkspaceImportRecord>, PmrError>;
+}
+
+// An import as found in a model, resolved as far as the repository allows.
+// The target_url is only set when the target is outside of the workspace,
+// i.e. in a submodule or referenced by an absolute URL.
+#[derive(Debug)]
+pub struct WorkspaceImport {
+    pub path: String,
+    pub href: String,
+    pub target_url: Option<String>,
+    pub target_commit_id: Option<String>,
+    pub target_path: Option<String>,
+}
+
+pub struct WorkspaceImportRecord {
+    pub id: i64,
+    pub workspace_id: i64,
+    pub commit_id: String,
+    pub path: String,
+    pub href: String,
+    pub target_url: Option<String>,
+    pub target_workspace_id: Option<i64>,
+    pub target_commit_id: Option<String>,
+    pub target_path: Option<String>,
+}
+
+impl std::fmt::Display for WorkspaceImportRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{} - {} - {}:{}:{}",
+            self.workspace_id,
+            &self.commit_id,
+            &self.path,
+            &self.href,
+            match self.target_workspace_id {
+                Some(v) => v.to_string(),
+                None => self.target_url.as_deref().unwrap_or("<unknown>").to_string(),
+            },
+            self.target_commit_id.as_deref().unwrap_or("<nil>"),
+            self.target_path.as_deref().unwrap_or("<nil>"),
+        )
+    }
+}
+
+// Link the imports that were left without a workspace to the one just
+// registered with the url, as part of the transaction registering it;
+// returns how many were linked.
+pub(crate) async fn link_workspace_imports(
+    tx: &mut Transaction<'_, Sqlite>, workspace_id: i64, url: &str, url_key: &str
+) -> Result<u64, PmrError> {
+    let linked = sqlx::query!(
+        r#"
+    UPDATE workspace_import
+    SET target_workspace_id = ?1
+    WHERE target_workspace_id IS NULL
+        AND target_url IS NOT NULL
+        AND (target_url_key = ?3 OR target_url = ?2)
+        "#,
+        workspace_id,
+        url,
+        url_key,
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    Ok(linked)
+}
+
+#[async_trait]
+impl WorkspaceImportBackend for SqliteBackend {
+    async fn index_workspace_imports(
+        &self, caller: &Caller, workspace_id: i64, commit_id: &str, imports: &[WorkspaceImport]
+    ) -> Result<u64, PmrError> {
+        WorkspaceAclBackend::check_workspace_permission(self, caller, workspace_id, WorkspaceRole::Editor).await?;
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+    DELETE FROM workspace_import
+    WHERE workspace_id = ?1 AND commit_id = ?2
+            "#,
+            workspace_id,
+            commit_id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        for import in imports {
+            // targets outside of this workspace are linked up by the key of
+            // their url, so any spelling of the url will do; by the url as
+            // is for workspaces that have yet to have a key. Anything that
+            // could not be resolved to a path is not linked at all.
+            let target_url_key = import.target_url.as_deref().and_then(|url| url_key(url).ok());
+            sqlx::query!(
+                r#"
+    INSERT INTO workspace_import (
+        workspace_id, commit_id, path, href,
+        target_url, target_workspace_id, target_commit_id, target_path, target_url_key
+    )
+    VALUES (
+        ?1, ?2, ?3, ?4, ?5,
+        CASE
+            WHEN ?5 IS NOT NULL THEN COALESCE(
+                (SELECT id FROM workspace WHERE url_key = ?8),
+                (SELECT id FROM workspace WHERE url = ?5)
+            )
+            WHEN ?7 IS NOT NULL THEN ?1
+        END,
+        ?6, ?7, ?8
+    )
+                "#,
+                workspace_id,
+                commit_id,
+                import.path,
+                import.href,
+                import.target_url,
+                import.target_commit_id,
+                import.target_path,
+                target_url_key,
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        record_event(
+            &mut tx, caller, "workspace_import.index", Some(workspace_id), &format!("workspace:{}", workspace_id),
+            None, Some(json!({ "commit_id": commit_id, "imports": imports.len() })),
+        ).await?;
+
+        tx.commit().await?;
+        Ok(imports.len() as u64)
+    }
+
+    async fn get_workspace_imports(
+        &self, caller: &Caller, workspace_id: i64, commit_id: &str, path: Option<&str>
+    ) -> Result<Vec<WorkspaceImportRecord>, PmrError> {
+        if !WorkspaceBackend::is_workspace_visible(self, caller, workspace_id).await? {
+            return Err(PmrError::NotFound(format!("workspace {}", workspace_id)));
+        }
+        let recs = sqlx::query_as!(WorkspaceImportRecord,
+            r#"
+    SELECT id, workspace_id, commit_id, path, href,
+        target_url, target_workspace_id, target_commit_id, target_path
+    FROM workspace_import
+    WHERE workspace_id = ?1 AND commit_id = ?2 AND (?3 IS NULL OR path = ?3)
+    ORDER BY id
+            "#,
+            workspace_id,
+            commit_id,
+            path,
+        )
+        .fetch_all(&*self.pool)
+        .await?;
+        Ok(recs)
+    }
+
+    async fn get_workspace_importers(
+        &self, caller: &Caller, workspace_id: i64, path: Option<&str>
+    ) -> Result<Vec<WorkspaceImportRecord>, PmrError> {
+        if !WorkspaceBackend::is_workspace_visible(self, caller, workspace_id).await? {
+            return Err(PmrError::NotFound(format!("workspace {}", workspace_id)));
+        }
+        let ts = Utc::now().timestamp();
+        let is_system = caller == &Caller::System;
+        let user_id = caller.user_id();
+        let recs = sqlx::query_as!(WorkspaceImportRecord,
+            r#"
+    SELECT workspace_import.id, workspace_import.workspace_id, commit_id, path, href,
+        target_url, target_workspace_id, target_commit_id, target_path
+    FROM workspace_import
+    JOIN workspace ON workspace.id = workspace_import.workspace_id
+    WHERE target_workspace_id = ?1 AND (?2 IS NULL OR target_path = ?2)
+        AND (?3 OR (workspace.archived IS NULL AND workspace.id IN (
+            SELECT workspace_id FROM workspace_reader
+            WHERE since <= ?4 AND (user_id IS NULL OR user_id = ?5)
+        )))
+    ORDER BY workspace_import.id
+            "#,
+            workspace_id,
+            path,
+            is_system,
+            ts,
+            user_id,
+        )
+        .fetch_all(&*self.pool)
+        .await?;
+        Ok(recs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_backend;
+
+    fn import(href: &str, target_url: Option<&str>, target_path: Option<&str>) -> WorkspaceImport {
+        WorkspaceImport {
+            path: "main.cellml".to_string(),
+            href: href.to_string(),
+            target_url: target_url.map(String::from),
+            target_commit_id: None,
+            target_path: target_path.map(String::from),
+        }
+    }
+
+    #[async_std::test]
+    async fn test_index_workspace_imports() {
+        let backend = test_backend().await;
+        let id = WorkspaceBackend::add_workspace(
+            &backend, &Caller::System, "https://example.com/org/main", "", "").await.unwrap();
+        let imports = vec![
+            import("a.cellml", None, Some("a.cellml")),
+            import("../outside.cellml", None, None),
+            import("lib/b.cellml", Some("https://example.com/org/lib.git"), Some("b.cellml")),
+        ];
+        backend.index_workspace_imports(&Caller::System, id, "c", &imports).await.unwrap();
+        let targets = |recs: Vec<WorkspaceImportRecord>| recs.into_iter()
+            .map(|rec| (rec.href, rec.target_workspace_id))
+            .collect::<Vec<_>>();
+        let recs = backend.get_workspace_imports(&Caller::System, id, "c", None).await.unwrap();
+        assert_eq!(targets(recs), vec![
+            ("a.cellml".to_string(), Some(id)),
+            ("../outside.cellml".to_string(), None),
+            ("lib/b.cellml".to_string(), None),
+        ]);
+
+        // linked once the workspace is registered, under any spelling
+        let lib_id = WorkspaceBackend::add_workspace(
+            &backend, &Caller::System, "http://Example.com/org/lib/", "", "").await.unwrap();
+        let recs = backend.get_workspace_imports(&Caller::System, id, "c", None).await.unwrap();
+        assert_eq!(targets(recs)[2], ("lib/b.cellml".to_string(), Some(lib_id)));
+    }
+}
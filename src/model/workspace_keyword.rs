@@ -0,0 +1,270 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_json::json;
+use sqlx::{Sqlite, Transaction};
+use std::fmt;
+
+use enum_primitive::FromPrimitive;
+
+use crate::model::audit::record_event;
+use crate::error::PmrError;
+use crate::model::backend::SqliteBackend;
+use crate::model::user::Caller;
+use crate::model::workspace::WorkspaceBackend;
+use crate::model::workspace_acl::{
+    WorkspaceAclBackend,
+    WorkspaceRole,
+};
+
+#[async_trait]
+pub trait WorkspaceKeywordBackend {
+    async fn add_workspace_keyword(&self, caller: &Caller, workspace_id: i64, keyword: &str) -> Result<bool, PmrError>;
+    async fn remove_workspace_keyword(&self, caller: &Caller, workspace_id: i64, keyword: &str) -> Result<bool, PmrError>;
+    // Replaces the keywords previously extracted for the workspace; those
+    // that were assigned are left as they are.
+    async fn index_workspace_keywords(
+        &self, caller: &Caller, workspace_id: i64, keywords: &[String]
+    ) -> Result<u64, PmrError>;
+    async fn get_workspace_keywords(&self, caller: &Caller, workspace_id: i64) -> Result<Vec<WorkspaceKeywordRecord>, PmrError>;
+    // Every keyword of the workspaces visible to the caller, with how many
+    // of those workspaces have it.
+    async fn list_keywords(&self, caller: &Caller) -> Result<Vec<KeywordRecord>, PmrError>;
+}
+
+enum_from_primitive! {
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum KeywordSource {
+    Assigned,
+    Extracted,
+    Unknown = -1,
+}
+}
+
+pub struct WorkspaceKeywordRecord {
+    pub name: String,
+    pub source: i64,
+}
+
+impl WorkspaceKeywordRecord {
+    pub fn source(&self) -> KeywordSource {
+        KeywordSource::from_i64(self.source).unwrap_or(KeywordSource::Unknown)
+    }
+}
+
+impl std::fmt::Display for WorkspaceKeywordRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {:?}",
+            &self.name,
+            self.source(),
+        )
+    }
+}
+
+pub struct KeywordRecord {
+    pub name: String,
+    pub workspaces: i64,
+}
+
+impl std::fmt::Display for KeywordRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {}",
+            &self.name,
+            self.workspaces,
+        )
+    }
+}
+
+// Keywords are compared case insensitively and regardless of spacing.
+pub fn normalize_keyword(keyword: &str) -> String {
+    keyword.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+// The id of the keyword, added if it isn't already known.
+pub(crate) async fn keyword_id(tx: &mut Transaction<'_, Sqlite>, name: &str) -> Result<i64, PmrError> {
+    sqlx::query!(
+        r#"
+INSERT INTO keyword ( name )
+VALUES ( ?1 )
+ON CONFLICT ( name ) DO NOTHING
+        "#,
+        name,
+    )
+    .execute(&mut *tx)
+    .await?;
+    let rec = sqlx::query!(
+        r#"
+SELECT id
+FROM keyword
+WHERE name = ?1
+        "#,
+        name,
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    Ok(rec.id)
+}
+
+#[async_trait]
+impl WorkspaceKeywordBackend for SqliteBackend {
+    async fn add_workspace_keyword(&self, caller: &Caller, workspace_id: i64, keyword: &str) -> Result<bool, PmrError> {
+        WorkspaceAclBackend::check_workspace_permission(self, caller, workspace_id, WorkspaceRole::Editor).await?;
+        let name = normalize_keyword(keyword);
+        if name.is_empty() {
+            return Err(PmrError::InvalidInput("keyword may not be empty".to_string()));
+        }
+        let mut tx = self.pool.begin().await?;
+
+        let keyword_id = keyword_id(&mut tx, &name).await?;
+        // assigning a keyword that was extracted keeps it past the next
+        // extraction
+        let rows_affected = sqlx::query!(
+            r#"
+INSERT INTO workspace_keyword ( workspace_id, keyword_id, source )
+VALUES ( ?1, ?2, 0 )
+ON CONFLICT ( workspace_id, keyword_id ) DO UPDATE SET source = 0 WHERE source != 0
+            "#,
+            workspace_id,
+            keyword_id,
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+
+        if rows_affected > 0 {
+            record_event(
+                &mut tx, caller, "workspace_keyword.add", Some(workspace_id),
+                &format!("workspace:{}", workspace_id), None, Some(json!({ "keyword": name })),
+            ).await?;
+        }
+
+        tx.commit().await?;
+        Ok(rows_affected > 0)
+    }
+
+    async fn remove_workspace_keyword(&self, caller: &Caller, workspace_id: i64, keyword: &str) -> Result<bool, PmrError> {
+        WorkspaceAclBackend::check_workspace_permission(self, caller, workspace_id, WorkspaceRole::Editor).await?;
+        let name = normalize_keyword(keyword);
+        let mut tx = self.pool.begin().await?;
+
+        let rows_affected = sqlx::query!(
+            r#"
+DELETE FROM workspace_keyword
+WHERE workspace_id = ?1 AND keyword_id = (
+    SELECT id FROM keyword WHERE name = ?2
+)
+            "#,
+            workspace_id,
+            name,
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected();
+
+        if rows_affected > 0 {
+            record_event(
+                &mut tx, caller, "workspace_keyword.remove", Some(workspace_id),
+                &format!("workspace:{}", workspace_id), Some(json!({ "keyword": name })), None,
+            ).await?;
+        }
+
+        tx.commit().await?;
+        Ok(rows_affected > 0)
+    }
+
+    async fn index_workspace_keywords(
+        &self, caller: &Caller, workspace_id: i64, keywords: &[String]
+    ) -> Result<u64, PmrError> {
+        WorkspaceAclBackend::check_workspace_permission(self, caller, workspace_id, WorkspaceRole::Editor).await?;
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+DELETE FROM workspace_keyword
+WHERE workspace_id = ?1 AND source = 1
+            "#,
+            workspace_id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        let mut count = 0;
+        let mut names = Vec::new();
+        for keyword in keywords {
+            let name = normalize_keyword(keyword);
+            if name.is_empty() {
+                continue;
+            }
+            let keyword_id = keyword_id(&mut tx, &name).await?;
+            count += sqlx::query!(
+                r#"
+INSERT INTO workspace_keyword ( workspace_id, keyword_id, source )
+VALUES ( ?1, ?2, 1 )
+ON CONFLICT ( workspace_id, keyword_id ) DO NOTHING
+                "#,
+                workspace_id,
+                keyword_id,
+            )
+            .execute(&mut tx)
+            .await?
+            .rows_affected();
+            names.push(name);
+        }
+
+        record_event(
+            &mut tx, caller, "workspace_keyword.index", Some(workspace_id),
+            &format!("workspace:{}", workspace_id), None, Some(json!({ "keywords": names })),
+        ).await?;
+
+        tx.commit().await?;
+        Ok(count)
+    }
+
+    async fn get_workspace_keywords(&self, caller: &Caller, workspace_id: i64) -> Result<Vec<WorkspaceKeywordRecord>, PmrError> {
+        if !WorkspaceBackend::is_workspace_visible(self, caller, workspace_id).await? {
+            return Err(PmrError::NotFound(format!("workspace {}", workspace_id)));
+        }
+        let recs = sqlx::query_as!(WorkspaceKeywordRecord,
+            r#"
+SELECT keyword.name, workspace_keyword.source
+FROM workspace_keyword
+JOIN keyword ON keyword.id = workspace_keyword.keyword_id
+WHERE workspace_keyword.workspace_id = ?1
+ORDER BY keyword.name
+            "#,
+            workspace_id,
+        )
+        .fetch_all(&*self.pool)
+        .await?;
+        Ok(recs)
+    }
+
+    async fn list_keywords(&self, caller: &Caller) -> Result<Vec<KeywordRecord>, PmrError> {
+        let ts = Utc::now().timestamp();
+        let is_system = caller == &Caller::System;
+        let user_id = caller.user_id();
+        let recs = sqlx::query_as!(KeywordRecord,
+            r#"
+SELECT keyword.name, COUNT(*) AS "workspaces!: i64"
+FROM keyword
+JOIN workspace_keyword ON workspace_keyword.keyword_id = keyword.id
+JOIN workspace ON workspace.id = workspace_keyword.workspace_id
+WHERE ?1 OR (workspace.archived IS NULL AND workspace.id IN (
+    SELECT workspace_id FROM workspace_reader
+    WHERE since <= ?2 AND (user_id IS NULL OR user_id = ?3)
+))
+GROUP BY keyword.id
+ORDER BY keyword.name
+            "#,
+            is_system,
+            ts,
+            user_id,
+        )
+        .fetch_all(&*self.pool)
+        .await?;
+        Ok(recs)
+    }
+}
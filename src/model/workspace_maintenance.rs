@@ -0,0 +1,125 @@
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use serde_json::json;
+use std::fmt;
+
+use crate::model::audit::record_event;
+use crate::error::PmrError;
+use crate::model::backend::SqliteBackend;
+use crate::model::user::Caller;
+use crate::model::workspace::WorkspaceBackend;
+use crate::model::workspace_acl::{
+    WorkspaceAclBackend,
+    WorkspaceRole,
+};
+
+#[async_trait]
+pub trait WorkspaceMaintenanceBackend {
+    async fn record_maintenance(
+        &self, caller: &Caller, workspace_id: i64, start: i64, size_before: i64, size_after: i64
+    ) -> Result<i64, PmrError>;
+    async fn get_workspace_maintenance_records(
+        &self, caller: &Caller, workspace_id: i64
+    ) -> Result<Vec<WorkspaceMaintenanceRecord>, PmrError>;
+}
+
+pub struct WorkspaceMaintenanceRecord {
+    pub id: i64,
+    pub workspace_id: i64,
+    pub start: i64,
+    pub end: i64,
+    pub size_before: i64,
+    pub size_after: i64,
+}
+
+impl std::fmt::Display for WorkspaceMaintenanceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {} - {} - {}",
+            Utc.timestamp_opt(self.start, 0).unwrap().to_rfc3339(),
+            Utc.timestamp_opt(self.end, 0).unwrap().to_rfc3339(),
+            self.size_before,
+            self.size_after,
+        )
+    }
+}
+
+#[async_trait]
+impl WorkspaceMaintenanceBackend for SqliteBackend {
+    async fn record_maintenance(
+        &self, caller: &Caller, workspace_id: i64, start: i64, size_before: i64, size_after: i64
+    ) -> Result<i64, PmrError> {
+        WorkspaceAclBackend::check_workspace_permission(self, caller, workspace_id, WorkspaceRole::Editor).await?;
+        let ts = Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
+
+        let id = sqlx::query!(
+            r#"
+INSERT INTO workspace_maintenance ( workspace_id, start, end, size_before, size_after )
+VALUES ( ?1, ?2, ?3, ?4, ?5 )
+            "#,
+            workspace_id,
+            start,
+            ts,
+            size_before,
+            size_after,
+        )
+        .execute(&mut tx)
+        .await?
+        .last_insert_rowid();
+
+        record_event(
+            &mut tx, caller, "workspace_maintenance.record", Some(workspace_id),
+            &format!("workspace_maintenance:{}", id), Some(json!({ "size": size_before })),
+            Some(json!({ "size": size_after })),
+        ).await?;
+
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    async fn get_workspace_maintenance_records(
+        &self, caller: &Caller, workspace_id: i64
+    ) -> Result<Vec<WorkspaceMaintenanceRecord>, PmrError> {
+        if !WorkspaceBackend::is_workspace_visible(self, caller, workspace_id).await? {
+            return Err(PmrError::NotFound(format!("workspace {}", workspace_id)));
+        }
+        let recs = sqlx::query_as!(WorkspaceMaintenanceRecord,
+            r#"
+SELECT id, workspace_id, start, end, size_before, size_after
+FROM workspace_maintenance
+WHERE workspace_id = ?1
+ORDER BY id
+            "#,
+            workspace_id,
+        )
+        .fetch_all(&*self.pool)
+        .await?;
+        Ok(recs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::workspace::WorkspaceVisibility;
+    use crate::utils::test_backend;
+
+    #[async_std::test]
+    async fn test_maintenance_records_visibility() {
+        let backend = test_backend().await;
+        let id = WorkspaceBackend::add_workspace(
+            &backend, &Caller::System, "https://example.com/repo", "", "").await.unwrap();
+        backend.record_maintenance(&Caller::System, id, 0, 200, 100).await.unwrap();
+        assert_eq!(backend.get_workspace_maintenance_records(&Caller::Anonymous, id).await.unwrap().len(), 1);
+
+        WorkspaceBackend::set_workspace_visibility(
+            &backend, &Caller::System, id, WorkspaceVisibility::Private, None).await.unwrap();
+        assert!(matches!(
+            backend.get_workspace_maintenance_records(&Caller::Anonymous, id).await,
+            Err(PmrError::NotFound(_)),
+        ));
+        assert_eq!(backend.get_workspace_maintenance_records(&Caller::System, id).await.unwrap().len(), 1);
+    }
+}
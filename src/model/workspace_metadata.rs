@@ -0,0 +1,118 @@
+use async_trait::async_trait;
+use serde_json::json;
+use std::fmt;
+
+use crate::cellml::rdf::Triple;
+use crate::error::PmrError;
+use crate::model::audit::record_event;
+use crate::model::backend::SqliteBackend;
+use crate::model::user::Caller;
+use crate::model::workspace::WorkspaceBackend;
+use crate::model::workspace_acl::{
+    WorkspaceAclBackend,
+    WorkspaceRole,
+};
+
+#[async_trait]
+pub trait WorkspaceMetadataBackend {
+    async fn index_workspace_metadata(
+        &self, caller: &Caller, workspace_id: i64, commit_id: &str, triples: &[(String, Triple)]
+    ) -> Result<u64, PmrError>;
+    async fn get_workspace_metadata(
+        &self, caller: &Caller, workspace_id: i64, commit_id: &str
+    ) -> Result<Vec<WorkspaceMetadataRecord>, PmrError>;
+}
+
+pub struct WorkspaceMetadataRecord {
+    pub id: i64,
+    pub workspace_id: i64,
+    pub commit_id: String,
+    pub path: String,
+    pub subject: String,
+    pub predicate: String,
+    pub object: String,
+    pub literal: bool,
+}
+
+impl std::fmt::Display for WorkspaceMetadataRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {} - {} - {}",
+            &self.path,
+            &self.subject,
+            &self.predicate,
+            &self.object,
+        )
+    }
+}
+
+#[async_trait]
+impl WorkspaceMetadataBackend for SqliteBackend {
+    // Replaces all metadata previously extracted for the commit; each
+    // triple is paired with the path of the file it came from.
+    async fn index_workspace_metadata(
+        &self, caller: &Caller, workspace_id: i64, commit_id: &str, triples: &[(String, Triple)]
+    ) -> Result<u64, PmrError> {
+        WorkspaceAclBackend::check_workspace_permission(self, caller, workspace_id, WorkspaceRole::Editor).await?;
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query!(
+            r#"
+    DELETE FROM workspace_metadata
+    WHERE workspace_id = ?1 AND commit_id = ?2
+            "#,
+            workspace_id,
+            commit_id,
+        )
+        .execute(&mut tx)
+        .await?;
+
+        for (path, triple) in triples {
+            sqlx::query!(
+                r#"
+    INSERT INTO workspace_metadata ( workspace_id, commit_id, path, subject, predicate, object, literal )
+    VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7 )
+                "#,
+                workspace_id,
+                commit_id,
+                path,
+                triple.subject,
+                triple.predicate,
+                triple.object,
+                triple.literal,
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        record_event(
+            &mut tx, caller, "workspace_metadata.index", Some(workspace_id), &format!("workspace:{}", workspace_id),
+            None, Some(json!({ "commit_id": commit_id, "triples": triples.len() })),
+        ).await?;
+
+        tx.commit().await?;
+        Ok(triples.len() as u64)
+    }
+
+    async fn get_workspace_metadata(
+        &self, caller: &Caller, workspace_id: i64, commit_id: &str
+    ) -> Result<Vec<WorkspaceMetadataRecord>, PmrError> {
+        if !WorkspaceBackend::is_workspace_visible(self, caller, workspace_id).await? {
+            return Err(PmrError::NotFound(format!("workspace {}", workspace_id)));
+        }
+        let recs = sqlx::query_as!(WorkspaceMetadataRecord,
+            r#"
+    SELECT id, workspace_id, commit_id, path, subject, predicate, object, literal AS "literal: bool"
+    FROM workspace_metadata
+    WHERE workspace_id = ?1 AND commit_id = ?2
+    ORDER BY id
+            "#,
+            workspace_id,
+            commit_id,
+        )
+        .fetch_all(&*self.pool)
+        .await?;
+        Ok(recs)
+    }
+}
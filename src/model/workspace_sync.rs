@@ -1,18 +1,26 @@
 use async_trait::async_trait;
-use anyhow::bail;
 use chrono::{TimeZone, Utc};
+use serde_json::json;
 use sqlx::sqlite::SqlitePool;
 
 use enum_primitive::FromPrimitive;
 
+use crate::model::audit::record_event;
+use crate::error::PmrError;
 use crate::model::backend::SqliteBackend;
+use crate::model::user::Caller;
+use crate::model::workspace::WorkspaceBackend;
+use crate::model::workspace_acl::{
+    WorkspaceAclBackend,
+    WorkspaceRole,
+};
 
 #[async_trait]
 pub trait WorkspaceSyncBackend {
-    async fn begin_sync(&self, workspace_id: i64) -> anyhow::Result<i64>;
-    async fn complete_sync(&self, id: i64, status: WorkspaceSyncStatus) -> anyhow::Result<bool>;
-    async fn fail_sync(&self, id: i64, msg: String) -> anyhow::Result<()>;
-    async fn get_workspaces_sync_records(&self, workspace_id: i64) -> anyhow::Result<Vec<WorkspaceSyncRecord>>;
+    async fn begin_sync(&self, caller: &Caller, workspace_id: i64) -> Result<i64, PmrError>;
+    async fn complete_sync(&self, caller: &Caller, id: i64, status: WorkspaceSyncStatus) -> Result<bool, PmrError>;
+    async fn fail_sync(&self, caller: &Caller, id: i64, msg: String) -> Result<(), PmrError>;
+    async fn get_workspaces_sync_records(&self, caller: &Caller, workspace_id: i64) -> Result<Vec<WorkspaceSyncRecord>, PmrError>;
 }
 
 enum_from_primitive! {
@@ -50,8 +58,10 @@ impl std::fmt::Display for WorkspaceSyncRecord {
 
 #[async_trait]
 impl WorkspaceSyncBackend for SqliteBackend {
-    async fn begin_sync(&self, workspace_id: i64) -> anyhow::Result<i64> {
+    async fn begin_sync(&self, caller: &Caller, workspace_id: i64) -> Result<i64, PmrError> {
+        WorkspaceAclBackend::check_workspace_permission(self, caller, workspace_id, WorkspaceRole::Editor).await?;
         let ts = Utc::now().timestamp();
+        let mut tx = self.pool.begin().await?;
 
         let id = sqlx::query!(
             r#"
@@ -62,16 +72,39 @@ impl WorkspaceSyncBackend for SqliteBackend {
             ts,
             WorkspaceSyncStatus::Running as i32,
         )
-        .execute(&*self.pool)
+        .execute(&mut tx)
         .await?
         .last_insert_rowid();
 
+        record_event(
+            &mut tx, caller, "workspace_sync.begin", Some(workspace_id), &format!("workspace_sync:{}", id),
+            None, Some(json!({ "status": WorkspaceSyncStatus::Running as i32 })),
+        ).await?;
+
+        tx.commit().await?;
         Ok(id)
     }
 
-    async fn complete_sync(&self, id: i64, status: WorkspaceSyncStatus) -> anyhow::Result<bool> {
+    async fn complete_sync(&self, caller: &Caller, id: i64, status: WorkspaceSyncStatus) -> Result<bool, PmrError> {
+        let rec = sqlx::query!(
+            r#"
+    SELECT workspace_id, status
+    FROM workspace_sync
+    WHERE id = ?1
+            "#,
+            id,
+        )
+        .fetch_optional(&*self.pool)
+        .await?;
+        let rec = match rec {
+            Some(rec) => rec,
+            None => return Ok(false),
+        };
+        WorkspaceAclBackend::check_workspace_permission(
+            self, caller, rec.workspace_id, WorkspaceRole::Editor).await?;
         let ts = Utc::now().timestamp();
         let status_ = status as i32;
+        let mut tx = self.pool.begin().await?;
 
         let rows_affected = sqlx::query!(
             r#"
@@ -83,19 +116,28 @@ impl WorkspaceSyncBackend for SqliteBackend {
             status_,
             id,
         )
-        .execute(&*self.pool)
+        .execute(&mut tx)
         .await?
         .rows_affected();
 
+        record_event(
+            &mut tx, caller, "workspace_sync.complete", Some(rec.workspace_id), &format!("workspace_sync:{}", id),
+            Some(json!({ "status": rec.status })), Some(json!({ "status": status_ })),
+        ).await?;
+
+        tx.commit().await?;
         Ok(rows_affected > 0)
     }
 
-    async fn fail_sync(&self, id: i64, msg: String) -> anyhow::Result<()> {
-        self.complete_sync(id, WorkspaceSyncStatus::Error).await?;
-        bail!(msg);
+    async fn fail_sync(&self, caller: &Caller, id: i64, msg: String) -> Result<(), PmrError> {
+        self.complete_sync(caller, id, WorkspaceSyncStatus::Error).await?;
+        Err(PmrError::SyncFailed(msg))
     }
 
-    async fn get_workspaces_sync_records(&self, workspace_id: i64) -> anyhow::Result<Vec<WorkspaceSyncRecord>> {
+    async fn get_workspaces_sync_records(&self, caller: &Caller, workspace_id: i64) -> Result<Vec<WorkspaceSyncRecord>, PmrError> {
+        if !WorkspaceBackend::is_workspace_visible(self, caller, workspace_id).await? {
+            return Err(PmrError::NotFound(format!("workspace {}", workspace_id)));
+        }
         let recs = sqlx::query_as!(WorkspaceSyncRecord,
             r#"
     SELECT id, workspace_id, start, end, status
@@ -1,13 +1,25 @@
 use async_trait::async_trait;
+use serde_json::json;
 use sqlx::sqlite::SqlitePool;
 use std::fmt;
 
+use crate::model::audit::record_event;
+use crate::error::PmrError;
 use crate::model::backend::SqliteBackend;
+use crate::model::user::Caller;
+use crate::model::workspace::WorkspaceBackend;
+use crate::model::workspace_acl::{
+    WorkspaceAclBackend,
+    WorkspaceRole,
+};
 
 #[async_trait]
 pub trait WorkspaceTagBackend {
-    async fn index_workspace_tag(&self, workspace_id: i64, name: &str, commit_id: &str) -> anyhow::Result<i64>;
-    async fn get_workspace_tags(&self, workspace_id: i64) -> anyhow::Result<Vec<WorkspaceTagRecord>>;
+    async fn index_workspace_tag(&self, caller: &Caller, workspace_id: i64, name: &str, commit_id: &str) -> Result<i64, PmrError>;
+    async fn index_workspace_tags(
+        &self, caller: &Caller, workspace_id: i64, tags: &[(String, String)]
+    ) -> Result<WorkspaceTagIndexSummary, PmrError>;
+    async fn get_workspace_tags(&self, caller: &Caller, workspace_id: i64) -> Result<Vec<WorkspaceTagRecord>, PmrError>;
 }
 
 pub struct WorkspaceTagRecord {
@@ -28,10 +40,33 @@ impl std::fmt::Display for WorkspaceTagRecord {
     }
 }
 
+// Outcome of indexing a batch of (name, commit_id) tags; a tag conflicts
+// when its name is already indexed against a different commit.
+#[derive(Debug, Default)]
+pub struct WorkspaceTagIndexSummary {
+    pub inserted: Vec<String>,
+    pub unchanged: Vec<String>,
+    pub conflicting: Vec<String>,
+}
+
+impl std::fmt::Display for WorkspaceTagIndexSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} inserted - {} unchanged - {} conflicting",
+            self.inserted.len(),
+            self.unchanged.len(),
+            self.conflicting.len(),
+        )
+    }
+}
+
 #[async_trait]
 impl WorkspaceTagBackend for SqliteBackend {
 
-    async fn index_workspace_tag(&self, workspace_id: i64, name: &str, commit_id: &str) -> anyhow::Result<i64> {
+    async fn index_workspace_tag(&self, caller: &Caller, workspace_id: i64, name: &str, commit_id: &str) -> Result<i64, PmrError> {
+        WorkspaceAclBackend::check_workspace_permission(self, caller, workspace_id, WorkspaceRole::Editor).await?;
+        let mut tx = self.pool.begin().await?;
         let id = sqlx::query!(
             r#"
     INSERT INTO workspace_tag ( workspace_id, name, commit_id )
@@ -42,15 +77,76 @@ impl WorkspaceTagBackend for SqliteBackend {
             name,
             commit_id,
         )
-        .execute(&*self.pool)
+        .execute(&mut tx)
         .await?
         .last_insert_rowid();
 
+        record_event(
+            &mut tx, caller, "workspace_tag.index", Some(workspace_id), &format!("workspace:{}", workspace_id),
+            None, Some(json!({ "name": name, "commit_id": commit_id })),
+        ).await?;
+
+        tx.commit().await?;
         Ok(id)
     }
-    // TODO create test so that the unique indexes are done correctly
 
-    async fn get_workspace_tags(&self, workspace_id: i64) -> anyhow::Result<Vec<WorkspaceTagRecord>> {
+    async fn index_workspace_tags(
+        &self, caller: &Caller, workspace_id: i64, tags: &[(String, String)]
+    ) -> Result<WorkspaceTagIndexSummary, PmrError> {
+        WorkspaceAclBackend::check_workspace_permission(self, caller, workspace_id, WorkspaceRole::Editor).await?;
+        let mut summary = WorkspaceTagIndexSummary::default();
+        let mut tx = self.pool.begin().await?;
+
+        for (name, commit_id) in tags {
+            let existing = sqlx::query!(
+                r#"
+    SELECT commit_id
+    FROM workspace_tag
+    WHERE workspace_id = ?1 AND name = ?2
+                "#,
+                workspace_id,
+                name,
+            )
+            .fetch_optional(&mut tx)
+            .await?;
+
+            match existing {
+                Some(rec) if &rec.commit_id == commit_id => summary.unchanged.push(name.clone()),
+                Some(_) => summary.conflicting.push(name.clone()),
+                None => {
+                    sqlx::query!(
+                        r#"
+    INSERT INTO workspace_tag ( workspace_id, name, commit_id )
+    VALUES ( ?1, ?2, ?3 )
+                        "#,
+                        workspace_id,
+                        name,
+                        commit_id,
+                    )
+                    .execute(&mut tx)
+                    .await?;
+                    summary.inserted.push(name.clone());
+                }
+            }
+        }
+
+        record_event(
+            &mut tx, caller, "workspace_tag.index", Some(workspace_id), &format!("workspace:{}", workspace_id),
+            None, Some(json!({
+                "inserted": summary.inserted,
+                "unchanged": summary.unchanged.len(),
+                "conflicting": summary.conflicting,
+            })),
+        ).await?;
+
+        tx.commit().await?;
+        Ok(summary)
+    }
+
+    async fn get_workspace_tags(&self, caller: &Caller, workspace_id: i64) -> Result<Vec<WorkspaceTagRecord>, PmrError> {
+        if !WorkspaceBackend::is_workspace_visible(self, caller, workspace_id).await? {
+            return Err(PmrError::NotFound(format!("workspace {}", workspace_id)));
+        }
         let recs = sqlx::query_as!(WorkspaceTagRecord,
             r#"
     SELECT id, workspace_id, name, commit_id
@@ -65,3 +161,39 @@ impl WorkspaceTagBackend for SqliteBackend {
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_backend;
+
+    fn tags(tags: &[(&str, &str)]) -> Vec<(String, String)> {
+        tags.iter().map(|(name, commit_id)| (name.to_string(), commit_id.to_string())).collect()
+    }
+
+    #[async_std::test]
+    async fn test_index_workspace_tags() {
+        let backend = test_backend().await;
+        let id = WorkspaceBackend::add_workspace(
+            &backend, &Caller::System, "https://example.com/repo", "", "").await.unwrap();
+
+        let summary = WorkspaceTagBackend::index_workspace_tags(
+            &backend, &Caller::System, id, &tags(&[("v1", "a"), ("v2", "b")])).await.unwrap();
+        assert_eq!(summary.to_string(), "2 inserted - 0 unchanged - 0 conflicting");
+
+        let summary = WorkspaceTagBackend::index_workspace_tags(
+            &backend, &Caller::System, id, &tags(&[("v1", "a"), ("v2", "c"), ("v3", "c")])).await.unwrap();
+        assert_eq!(summary.inserted, vec!["v3"]);
+        assert_eq!(summary.unchanged, vec!["v1"]);
+        assert_eq!(summary.conflicting, vec!["v2"]);
+
+        // indexing a tag that is already there adds nothing
+        WorkspaceTagBackend::index_workspace_tag(&backend, &Caller::System, id, "v1", "a").await.unwrap();
+        let mut recs = WorkspaceTagBackend::get_workspace_tags(&backend, &Caller::System, id).await.unwrap()
+            .into_iter()
+            .map(|rec| rec.to_string())
+            .collect::<Vec<_>>();
+        recs.sort();
+        assert_eq!(recs, vec!["a - v1", "b - v2", "c - v3"]);
+    }
+}
@@ -0,0 +1,419 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt;
+
+use enum_primitive::FromPrimitive;
+
+use crate::model::audit::record_event;
+use crate::error::PmrError;
+use crate::model::backend::SqliteBackend;
+use crate::model::user::Caller;
+use crate::model::workspace::{WorkspaceBackend, WorkspaceVisibility};
+use crate::model::workspace_import::link_workspace_imports;
+use crate::model::workspace_keyword::{KeywordSource, keyword_id, normalize_keyword};
+use crate::model::workspace_sync::WorkspaceSyncStatus;
+use crate::model::workspace_url::url_key;
+
+pub const TRANSFER_VERSION: i64 = 1;
+
+// Moves workspace records between instances; the repos themselves are not
+// included, and are cloned again by syncing the imported workspaces.
+#[async_trait]
+pub trait WorkspaceTransferBackend {
+    // Export the given workspaces, or all that are visible to the caller.
+    async fn export_workspaces(&self, caller: &Caller, ids: &[i64]) -> Result<WorkspaceTransfer, PmrError>;
+    async fn import_workspaces(
+        &self, caller: &Caller, transfer: &WorkspaceTransfer
+    ) -> Result<WorkspaceTransferSummary, PmrError>;
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkspaceTransfer {
+    pub version: i64,
+    pub exported: i64,
+    pub workspaces: Vec<TransferWorkspace>,
+}
+
+// Ids are those of the exporting instance, and are remapped on import.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferWorkspace {
+    pub id: i64,
+    pub url: String,
+    pub superceded_by_id: Option<i64>,
+    pub description: Option<String>,
+    pub long_description: Option<String>,
+    pub created: i64,
+    pub visibility: i64,
+    pub embargo_until: Option<i64>,
+    pub archived: Option<i64>,
+    pub tags: Vec<TransferTag>,
+    pub sync: Vec<TransferSync>,
+    // absent from documents exported before keywords were kept
+    #[serde(default)]
+    pub keywords: Vec<TransferKeyword>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferTag {
+    pub name: String,
+    pub commit_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferSync {
+    pub start: i64,
+    pub end: Option<i64>,
+    pub status: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TransferKeyword {
+    pub name: String,
+    pub source: i64,
+}
+
+// Pairs of (exported id, id on this instance); a duplicate is a workspace
+// whose url was already registered here, which is left untouched.
+#[derive(Debug, Default)]
+pub struct WorkspaceTransferSummary {
+    pub imported: Vec<(i64, i64)>,
+    pub duplicates: Vec<(i64, i64)>,
+}
+
+impl std::fmt::Display for WorkspaceTransferSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} imported - {} duplicates",
+            self.imported.len(),
+            self.duplicates.len(),
+        )
+    }
+}
+
+impl TransferWorkspace {
+    // The values that the queries on this instance take as given, which
+    // must not be taken on trust from a document from anywhere.
+    fn validate(&self) -> Result<(), PmrError> {
+        let invalid = |msg: String| Err(PmrError::InvalidInput(format!("workspace {}: {}", self.id, msg)));
+        match WorkspaceVisibility::from_i64(self.visibility) {
+            None => return invalid(format!("unknown visibility {}", self.visibility)),
+            Some(WorkspaceVisibility::Embargoed) if self.embargo_until.is_none() =>
+                return invalid("embargoed without an end date for the embargo".to_string()),
+            _ => (),
+        }
+        if let Some(archived) = self.archived.filter(|archived| *archived < 0) {
+            return invalid(format!("invalid archived time {}", archived));
+        }
+        for sync in self.sync.iter() {
+            match WorkspaceSyncStatus::from_i64(sync.status) {
+                None | Some(WorkspaceSyncStatus::Unknown) => return invalid(format!("unknown sync status {}", sync.status)),
+                _ => (),
+            }
+        }
+        for keyword in self.keywords.iter() {
+            match KeywordSource::from_i64(keyword.source) {
+                None | Some(KeywordSource::Unknown) => return invalid(format!("unknown keyword source {}", keyword.source)),
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl WorkspaceTransferBackend for SqliteBackend {
+    async fn export_workspaces(&self, caller: &Caller, ids: &[i64]) -> Result<WorkspaceTransfer, PmrError> {
+        let mut workspaces = Vec::new();
+        for workspace in WorkspaceBackend::list_workspaces(self, caller).await? {
+            if !ids.is_empty() && !ids.contains(&workspace.id) {
+                continue;
+            }
+            let rec = sqlx::query!(
+                r#"
+SELECT superceded_by_id, long_description, created
+FROM workspace
+WHERE id = ?1
+                "#,
+                workspace.id,
+            )
+            .fetch_one(&*self.pool)
+            .await?;
+            let tags = sqlx::query_as!(TransferTag,
+                r#"
+SELECT name, commit_id
+FROM workspace_tag
+WHERE workspace_id = ?1
+ORDER BY id
+                "#,
+                workspace.id,
+            )
+            .fetch_all(&*self.pool)
+            .await?;
+            let sync = sqlx::query_as!(TransferSync,
+                r#"
+SELECT start, end, status
+FROM workspace_sync
+WHERE workspace_id = ?1
+ORDER BY id
+                "#,
+                workspace.id,
+            )
+            .fetch_all(&*self.pool)
+            .await?;
+            let keywords = sqlx::query_as!(TransferKeyword,
+                r#"
+SELECT keyword.name, workspace_keyword.source
+FROM workspace_keyword
+JOIN keyword ON keyword.id = workspace_keyword.keyword_id
+WHERE workspace_keyword.workspace_id = ?1
+ORDER BY keyword.name
+                "#,
+                workspace.id,
+            )
+            .fetch_all(&*self.pool)
+            .await?;
+            workspaces.push(TransferWorkspace {
+                id: workspace.id,
+                url: workspace.url,
+                superceded_by_id: rec.superceded_by_id,
+                description: workspace.description,
+                long_description: rec.long_description,
+                created: rec.created,
+                visibility: workspace.visibility,
+                embargo_until: workspace.embargo_until,
+                archived: workspace.archived,
+                tags: tags,
+                sync: sync,
+                keywords: keywords,
+            });
+        }
+        for id in ids {
+            if !workspaces.iter().any(|workspace| workspace.id == *id) {
+                return Err(PmrError::NotFound(format!("workspace {}", id)));
+            }
+        }
+
+        Ok(WorkspaceTransfer {
+            version: TRANSFER_VERSION,
+            exported: Utc::now().timestamp(),
+            workspaces: workspaces,
+        })
+    }
+
+    async fn import_workspaces(
+        &self, caller: &Caller, transfer: &WorkspaceTransfer
+    ) -> Result<WorkspaceTransferSummary, PmrError> {
+        // imported workspaces have no owner, so only the system may import
+        if caller != &Caller::System {
+            return Err(PmrError::PermissionDenied(format!("{:?} may not import workspaces", caller)));
+        }
+        if transfer.version != TRANSFER_VERSION {
+            return Err(PmrError::InvalidInput(format!("unsupported workspace transfer version {}", transfer.version)));
+        }
+        for workspace in transfer.workspaces.iter() {
+            workspace.validate()?;
+        }
+        let mut summary = WorkspaceTransferSummary::default();
+        let mut ids = HashMap::new();
+        let mut tx = self.pool.begin().await?;
+
+        for workspace in transfer.workspaces.iter() {
+            let url_key = url_key(&workspace.url)?;
+            let existing = sqlx::query!(
+                r#"
+SELECT id
+FROM workspace
+WHERE url_key = ?1 OR url = ?2
+                "#,
+                url_key,
+                workspace.url,
+            )
+            .fetch_optional(&mut tx)
+            .await?;
+            if let Some(rec) = existing {
+                ids.insert(workspace.id, rec.id);
+                summary.duplicates.push((workspace.id, rec.id));
+                continue;
+            }
+
+            let id = sqlx::query!(
+                r#"
+INSERT INTO workspace ( url, url_key, description, long_description, created, visibility, embargo_until, archived )
+VALUES ( ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8 )
+                "#,
+                workspace.url,
+                url_key,
+                workspace.description,
+                workspace.long_description,
+                workspace.created,
+                workspace.visibility,
+                workspace.embargo_until,
+                workspace.archived,
+            )
+            .execute(&mut tx)
+            .await?
+            .last_insert_rowid();
+            link_workspace_imports(&mut tx, id, &workspace.url, &url_key).await?;
+
+            // only the first of any tags of the same name is kept
+            for tag in workspace.tags.iter() {
+                sqlx::query!(
+                    r#"
+INSERT INTO workspace_tag ( workspace_id, name, commit_id )
+VALUES ( ?1, ?2, ?3 )
+ON CONFLICT DO NOTHING
+                    "#,
+                    id,
+                    tag.name,
+                    tag.commit_id,
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+            for sync in workspace.sync.iter() {
+                // a sync still running on the other instance will never
+                // be completed here, and would hold up every sync after it
+                let status = match WorkspaceSyncStatus::from_i64(sync.status) {
+                    Some(WorkspaceSyncStatus::Running) => WorkspaceSyncStatus::Error as i64,
+                    _ => sync.status,
+                };
+                sqlx::query!(
+                    r#"
+INSERT INTO workspace_sync ( workspace_id, start, end, status )
+VALUES ( ?1, ?2, ?3, ?4 )
+                    "#,
+                    id,
+                    sync.start,
+                    sync.end,
+                    status,
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+            for keyword in workspace.keywords.iter() {
+                let name = normalize_keyword(&keyword.name);
+                if name.is_empty() {
+                    continue;
+                }
+                let keyword_id = keyword_id(&mut tx, &name).await?;
+                sqlx::query!(
+                    r#"
+INSERT INTO workspace_keyword ( workspace_id, keyword_id, source )
+VALUES ( ?1, ?2, ?3 )
+ON CONFLICT ( workspace_id, keyword_id ) DO NOTHING
+                    "#,
+                    id,
+                    keyword_id,
+                    keyword.source,
+                )
+                .execute(&mut tx)
+                .await?;
+            }
+
+            record_event(
+                &mut tx, caller, "workspace.import", Some(id), &format!("workspace:{}", id), None,
+                Some(json!({
+                    "url": workspace.url,
+                    "exported_id": workspace.id,
+                    "tags": workspace.tags.len(),
+                    "sync": workspace.sync.len(),
+                    "keywords": workspace.keywords.len(),
+                })),
+            ).await?;
+            ids.insert(workspace.id, id);
+            summary.imported.push((workspace.id, id));
+        }
+
+        // only now are the ids of everything that may supercede known
+        for workspace in transfer.workspaces.iter() {
+            let id = ids[&workspace.id];
+            let superceded_by_id = workspace.superceded_by_id.and_then(|v| ids.get(&v).copied());
+            if superceded_by_id.is_none() || !summary.imported.contains(&(workspace.id, id)) {
+                continue;
+            }
+            sqlx::query!(
+                r#"
+UPDATE workspace
+SET superceded_by_id = ?1
+WHERE id = ?2
+                "#,
+                superceded_by_id,
+                id,
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::workspace_tag::WorkspaceTagBackend;
+    use crate::utils::test_backend;
+
+    fn transfer_workspace(id: i64, url: &str) -> TransferWorkspace {
+        TransferWorkspace {
+            id: id,
+            url: url.to_string(),
+            superceded_by_id: None,
+            description: None,
+            long_description: None,
+            created: 0,
+            visibility: WorkspaceVisibility::Public as i64,
+            embargo_until: None,
+            archived: None,
+            tags: vec![],
+            sync: vec![],
+            keywords: vec![],
+        }
+    }
+
+    fn transfer(workspaces: Vec<TransferWorkspace>) -> WorkspaceTransfer {
+        WorkspaceTransfer { version: TRANSFER_VERSION, exported: 0, workspaces: workspaces }
+    }
+
+    #[async_std::test]
+    async fn test_import_workspaces() {
+        let backend = test_backend().await;
+
+        let mut workspace = transfer_workspace(1, "https://example.com/invalid");
+        workspace.visibility = 7;
+        assert!(matches!(
+            backend.import_workspaces(&Caller::System, &transfer(vec![workspace])).await,
+            Err(PmrError::InvalidInput(_))));
+
+        let mut workspace = transfer_workspace(1, "https://example.com/invalid");
+        workspace.visibility = WorkspaceVisibility::Embargoed as i64;
+        assert!(matches!(
+            backend.import_workspaces(&Caller::System, &transfer(vec![workspace])).await,
+            Err(PmrError::InvalidInput(_))));
+
+        // nothing is imported when any one of the workspaces is invalid
+        let mut workspace = transfer_workspace(2, "https://example.com/invalid");
+        workspace.keywords.push(TransferKeyword { name: "cell".to_string(), source: 7 });
+        let workspaces = vec![transfer_workspace(1, "https://example.com/valid"), workspace];
+        assert!(matches!(
+            backend.import_workspaces(&Caller::System, &transfer(workspaces)).await,
+            Err(PmrError::InvalidInput(_))));
+
+        let mut workspace = transfer_workspace(1, "https://example.com/valid");
+        workspace.tags = vec![
+            TransferTag { name: "v1".to_string(), commit_id: "a".repeat(40) },
+            TransferTag { name: "v1".to_string(), commit_id: "b".repeat(40) },
+            TransferTag { name: "v1".to_string(), commit_id: "a".repeat(40) },
+        ];
+        let summary = backend.import_workspaces(&Caller::System, &transfer(vec![workspace])).await.unwrap();
+        assert_eq!(summary.imported.len(), 1);
+        let tags = backend.get_workspace_tags(&Caller::System, summary.imported[0].1).await.unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].commit_id, "a".repeat(40));
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt;
+use url::Url;
+
+use crate::model::user::Caller;
+
+#[derive(Debug, PartialEq)]
+pub enum WorkspaceUrlError {
+    Invalid(String),
+    UnsupportedScheme(String),
+    // local paths give access to the server's own filesystem
+    LocalPathNotAllowed,
+    Unreachable(String),
+    // the same remote is already registered as this workspace
+    Conflict(i64),
+}
+
+impl std::fmt::Display for WorkspaceUrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkspaceUrlError::Invalid(e) => write!(f, "invalid url: {}", e),
+            WorkspaceUrlError::UnsupportedScheme(s) => write!(f, "unsupported url scheme '{}'", s),
+            WorkspaceUrlError::LocalPathNotAllowed => write!(f, "local paths may only be registered by the system"),
+            WorkspaceUrlError::Unreachable(e) => write!(f, "url is unreachable: {}", e),
+            WorkspaceUrlError::Conflict(id) => write!(f, "url is already registered as workspace {}", id),
+        }
+    }
+}
+
+impl std::error::Error for WorkspaceUrlError {}
+
+// Accepts urls as git does, including absolute local paths and the scp
+// style user@host:path shorthand for ssh.
+fn parse_url(url: &str) -> Result<Url, WorkspaceUrlError> {
+    let url = url.trim();
+    if url.starts_with('/') {
+        return Url::from_file_path(url)
+            .map_err(|_| WorkspaceUrlError::Invalid(format!("bad local path {}", url)));
+    }
+    let url = if url.contains("://") {
+        url.to_string()
+    }
+    else {
+        match url.split_once(':') {
+            Some((host, path)) if !host.is_empty() && !host.contains('/') =>
+                format!("ssh://{}/{}", host, path.trim_start_matches('/')),
+            _ => return Err(WorkspaceUrlError::Invalid(format!("{} is not an absolute url or path", url))),
+        }
+    };
+    Url::parse(&url).map_err(|e| WorkspaceUrlError::Invalid(e.to_string()))
+}
+
+// The form of the url that every spelling of the same remote shares, used
+// to stop the same remote being registered twice: http and https, default
+// ports, user names, case of the host and trailing slashes or .git are
+// not told apart.
+pub fn url_key(url: &str) -> Result<String, WorkspaceUrlError> {
+    let parsed = parse_url(url)?;
+    let scheme = match parsed.scheme() {
+        "http" | "https" => "https",
+        "ssh" | "git" | "file" => parsed.scheme(),
+        scheme => return Err(WorkspaceUrlError::UnsupportedScheme(scheme.to_string())),
+    };
+    let host = parsed.host_str().unwrap_or("").to_lowercase();
+    if host.is_empty() && scheme != "file" {
+        return Err(WorkspaceUrlError::Invalid("no host in url".to_string()));
+    }
+    let port = match (scheme, parsed.port()) {
+        ("https", Some(80)) | ("https", Some(443)) | ("ssh", Some(22)) | ("git", Some(9418)) => None,
+        (_, port) => port,
+    };
+    let path = parsed.path().trim_end_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path).trim_end_matches('/');
+    Ok(match port {
+        Some(port) => format!("{}://{}:{}{}", scheme, host, port, path),
+        None => format!("{}://{}{}", scheme, host, path),
+    })
+}
+
+pub fn is_local_url(url: &str) -> bool {
+    parse_url(url).map_or(false, |parsed| parsed.scheme() == "file")
+}
+
+// Validate a url about to be registered by the caller, returning its key.
+pub fn check_workspace_url(caller: &Caller, url: &str) -> Result<String, WorkspaceUrlError> {
+    let key = url_key(url)?;
+    if caller != &Caller::System && is_local_url(url) {
+        return Err(WorkspaceUrlError::LocalPathNotAllowed);
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(parse_url("git@github.com:org/repo.git").unwrap().as_str(), "ssh://git@github.com/org/repo.git");
+        assert_eq!(parse_url(" https://example.com/repo ").unwrap().as_str(), "https://example.com/repo");
+        assert_eq!(parse_url("/srv/git/repo").unwrap().as_str(), "file:///srv/git/repo");
+        assert!(matches!(parse_url("repo"), Err(WorkspaceUrlError::Invalid(_))));
+        assert!(matches!(parse_url("./repo"), Err(WorkspaceUrlError::Invalid(_))));
+        assert!(matches!(parse_url(":repo"), Err(WorkspaceUrlError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_url_key() {
+        let key = "https://example.com/org/repo";
+        for url in &[
+            "https://example.com/org/repo",
+            "http://example.com/org/repo",
+            "https://Example.COM/org/repo.git",
+            "https://example.com/org/repo/",
+            "https://example.com/org/repo.git/",
+            "https://user@example.com:443/org/repo",
+            "http://example.com:80/org/repo",
+        ] {
+            assert_eq!(url_key(url).unwrap(), key, "{}", url);
+        }
+        assert_eq!(url_key("git@example.com:org/repo.git").unwrap(), "ssh://example.com/org/repo");
+        assert_eq!(url_key("ssh://git@example.com:22/org/repo").unwrap(), "ssh://example.com/org/repo");
+        assert_eq!(url_key("https://example.com:8443/org/repo").unwrap(), "https://example.com:8443/org/repo");
+        // only the host is folded, as paths may well be case sensitive
+        assert_eq!(url_key("https://example.com/Org/Repo").unwrap(), "https://example.com/Org/Repo");
+        assert_eq!(url_key("/srv/git/repo.git").unwrap(), "file:///srv/git/repo");
+        assert!(matches!(url_key("ftp://example.com/repo"), Err(WorkspaceUrlError::UnsupportedScheme(_))));
+        assert!(matches!(url_key("ssh:///repo"), Err(WorkspaceUrlError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_check_workspace_url() {
+        assert!(check_workspace_url(&Caller::System, "/srv/git/repo").is_ok());
+        assert_eq!(
+            check_workspace_url(&Caller::User(1), "/srv/git/repo"),
+            Err(WorkspaceUrlError::LocalPathNotAllowed),
+        );
+        assert!(check_workspace_url(&Caller::User(1), "https://example.com/repo").is_ok());
+    }
+}
@@ -0,0 +1,198 @@
+use async_std::task;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::PmrError;
+use crate::model::backend::SqliteBackend;
+use crate::model::user::Caller;
+use crate::model::workspace::WorkspaceBackend;
+use crate::repo::git::lock_workspace;
+
+pub const MANIFEST_VERSION: i64 = 1;
+const MANIFEST_NAME: &str = "manifest.json";
+const DATABASE_NAME: &str = "workspace.db";
+const REPOS_DIR: &str = "repos";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub version: i64,
+    pub created: i64,
+    pub database: BackupFile,
+    pub repos: Vec<BackupRepo>,
+}
+
+// Paths are relative to the backup directory.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub path: PathBuf,
+    pub sha256: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupRepo {
+    pub workspace_id: i64,
+    #[serde(flatten)]
+    pub file: BackupFile,
+}
+
+// Write a snapshot of the database and an archive of every repo to dest,
+// which must not already exist, along with a manifest of their checksums.
+pub async fn backup(
+    backend: &SqliteBackend, caller: &Caller, git_root: &Path, dest: &Path
+) -> Result<BackupManifest, PmrError> {
+    if caller != &Caller::System {
+        return Err(PmrError::PermissionDenied(format!("{:?} may not make backups", caller)));
+    }
+    if dest.exists() {
+        return Err(PmrError::InvalidInput(format!("backup destination {:?} already exists", dest)));
+    }
+    std::fs::create_dir_all(dest.join(REPOS_DIR))?;
+
+    // the database goes first, so the repos are never behind it; a repo
+    // that is ahead only has tags that are not yet indexed
+    info!("Taking snapshot of database...");
+    let database_path = dest.join(DATABASE_NAME);
+    sqlx::query("VACUUM INTO ?1")
+        .bind(database_path.to_string_lossy().into_owned())
+        .execute(&*backend.pool)
+        .await?;
+    let database = BackupFile {
+        path: PathBuf::from(DATABASE_NAME),
+        sha256: task::spawn_blocking(move || sha256_file(&database_path)).await?,
+    };
+
+    let mut repos = Vec::new();
+    for workspace in WorkspaceBackend::list_workspaces(backend, caller).await? {
+        let repo_dir = git_root.join(workspace.id.to_string());
+        if !repo_dir.exists() {
+            warn!("no repo for workspace {}, skipping", workspace.id);
+            continue;
+        }
+        let path = Path::new(REPOS_DIR).join(format!("{}.tar", workspace.id));
+        let archive_path = dest.join(&path);
+        info!("Archiving repository at {:?}...", repo_dir);
+        let _lock = lock_workspace(git_root, workspace.id).await?;
+        let sha256 = task::spawn_blocking(move || -> Result<String, PmrError> {
+            let mut builder = tar::Builder::new(File::create(&archive_path)?);
+            builder.append_dir_all(".", &repo_dir)?;
+            builder.into_inner()?.sync_all()?;
+            sha256_file(&archive_path)
+        }).await?;
+        repos.push(BackupRepo {
+            workspace_id: workspace.id,
+            file: BackupFile { path: path, sha256: sha256 },
+        });
+    }
+
+    let manifest = BackupManifest {
+        version: MANIFEST_VERSION,
+        created: Utc::now().timestamp(),
+        database: database,
+        repos: repos,
+    };
+    std::fs::write(dest.join(MANIFEST_NAME), serde_json::to_string_pretty(&manifest)?)?;
+    Ok(manifest)
+}
+
+// Restore a backup made by backup onto a fresh instance, i.e. where the
+// database does not exist and the git root has no repos. Every checksum
+// is verified before anything is written.
+pub async fn restore(src: &Path, database_path: &Path, git_root: &Path) -> Result<BackupManifest, PmrError> {
+    let manifest: BackupManifest = serde_json::from_slice(&std::fs::read(src.join(MANIFEST_NAME))?)?;
+    if manifest.version != MANIFEST_VERSION {
+        return Err(PmrError::InvalidDocument(format!("unsupported backup manifest version {}", manifest.version)));
+    }
+    if database_path.exists() {
+        return Err(PmrError::InvalidInput(format!("database {:?} already exists", database_path)));
+    }
+    for repo in manifest.repos.iter() {
+        if git_root.join(repo.workspace_id.to_string()).exists() {
+            return Err(PmrError::InvalidInput(format!(
+                "repo for workspace {} already exists under {:?}", repo.workspace_id, git_root)));
+        }
+    }
+    // the manifest may have come from anywhere, so none of its paths may
+    // lead outside of the backup directory
+    let files = std::iter::once(&manifest.database).chain(manifest.repos.iter().map(|repo| &repo.file));
+    for file in files {
+        if file.path.as_os_str().is_empty()
+            || !file.path.components().all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(PmrError::InvalidDocument(format!("invalid path {:?} in backup manifest", file.path)));
+        }
+    }
+
+    let src = src.to_path_buf();
+    let database_path = database_path.to_path_buf();
+    let git_root = git_root.to_path_buf();
+    task::spawn_blocking(move || -> Result<BackupManifest, PmrError> {
+        let files = std::iter::once(&manifest.database).chain(manifest.repos.iter().map(|repo| &repo.file));
+        for file in files {
+            if sha256_file(&src.join(&file.path))? != file.sha256 {
+                return Err(PmrError::InvalidDocument(format!("checksum mismatch for {:?}", file.path)));
+            }
+        }
+
+        info!("Restoring database to {:?}", database_path);
+        std::fs::copy(src.join(&manifest.database.path), &database_path)?;
+        for repo in manifest.repos.iter() {
+            let repo_dir = git_root.join(repo.workspace_id.to_string());
+            info!("Restoring repository to {:?}", repo_dir);
+            std::fs::create_dir_all(&repo_dir)?;
+            tar::Archive::new(File::open(src.join(&repo.file.path))?).unpack(&repo_dir)?;
+        }
+        Ok(manifest)
+    }).await
+}
+
+// The path to the database file named by a sqlite: database url.
+pub fn database_path(url: &str) -> Result<PathBuf, PmrError> {
+    let path = match url.strip_prefix("sqlite://").or_else(|| url.strip_prefix("sqlite:")) {
+        Some(path) => path.split('?').next().unwrap_or(path),
+        None => return Err(PmrError::InvalidInput(format!("not a sqlite database url: {}", url))),
+    };
+    if path.is_empty() || path == ":memory:" {
+        return Err(PmrError::InvalidInput(format!("database url {} does not name a file", url)));
+    }
+    Ok(PathBuf::from(path))
+}
+
+fn sha256_file(path: &Path) -> Result<String, PmrError> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_database_path() {
+        assert_eq!(database_path("sqlite:workspace.db").unwrap(), PathBuf::from("workspace.db"));
+        assert_eq!(database_path("sqlite:///srv/pmr/workspace.db").unwrap(), PathBuf::from("/srv/pmr/workspace.db"));
+        assert_eq!(database_path("sqlite://workspace.db?mode=rwc").unwrap(), PathBuf::from("workspace.db"));
+        assert!(matches!(database_path("sqlite::memory:"), Err(PmrError::InvalidInput(_))));
+        assert!(matches!(database_path("sqlite:"), Err(PmrError::InvalidInput(_))));
+        assert!(matches!(database_path("postgres://localhost/pmr"), Err(PmrError::InvalidInput(_))));
+    }
+
+    #[async_std::test]
+    async fn test_restore_path_escape() {
+        let dir = tempfile::tempdir().unwrap();
+        for path in &["../workspace.db", "/etc/passwd", "./workspace.db", ""] {
+            let manifest = BackupManifest {
+                version: MANIFEST_VERSION,
+                created: 0,
+                database: BackupFile { path: PathBuf::from(path), sha256: "".to_string() },
+                repos: vec![],
+            };
+            std::fs::write(dir.path().join(MANIFEST_NAME), serde_json::to_string(&manifest).unwrap()).unwrap();
+            let result = restore(dir.path(), &dir.path().join("restored.db"), &dir.path().join("git")).await;
+            assert!(matches!(result, Err(PmrError::InvalidDocument(_))), "{}", path);
+        }
+    }
+}
@@ -0,0 +1,282 @@
+use git2::Repository;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::error::PmrError;
+use crate::repo::git::TreeListingEntry;
+
+// Maximum number of workspaces whose repositories will be kept open.
+const REPO_CACHE_CAPACITY: usize = 64;
+// Maximum number of idle handles kept open for any one repository; more
+// may be in use at once, as there are git threads to use them.
+const REPO_CACHE_HANDLES: usize = 4;
+// Maximum number of entries, across all tree listings, that will be kept
+// around; a listing of a large repo may well have tens of thousands.
+const TREE_LISTING_CACHE_CAPACITY: usize = 262144;
+
+static REPO_CACHE: RepoCache = RepoCache::new(REPO_CACHE_CAPACITY, REPO_CACHE_HANDLES);
+static TREE_LISTING_CACHE: TreeListingCache = TreeListingCache::new(TREE_LISTING_CACHE_CAPACITY);
+
+type RepoHandles = Arc<Mutex<Vec<Repository>>>;
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // the lists are always left consistent, so a poisoned lock is safe
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+struct RepoCacheEntry {
+    workspace_id: i64,
+    repo_dir: PathBuf,
+    idle: RepoHandles,
+}
+
+// A bounded LRU cache of opened bare repositories, keyed by workspace id.
+// Entries are kept in order of use, with the most recently used at the end.
+// A git2 Repository may only be used by one thread at a time, so each
+// entry is a pool of idle handles: one is taken out for the length of an
+// operation and another opened if none are idle, so that operations on
+// the same workspace never wait on each other.
+pub struct RepoCache {
+    capacity: usize,
+    handles: usize,
+    entries: Mutex<Vec<RepoCacheEntry>>,
+}
+
+impl RepoCache {
+    pub const fn new(capacity: usize, handles: usize) -> RepoCache {
+        RepoCache {
+            capacity: capacity,
+            handles: handles,
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    // The idle handles of the workspace, if it has an entry for the same
+    // repo_dir, marking it as the most recently used.
+    fn idle(&self, workspace_id: i64, repo_dir: &Path) -> Option<RepoHandles> {
+        let mut entries = lock(&self.entries);
+        let pos = entries.iter().position(|entry| entry.workspace_id == workspace_id)?;
+        let entry = entries.remove(pos);
+        // a different git_root for the same id is treated as a miss
+        if entry.repo_dir != repo_dir {
+            return None;
+        }
+        let idle = entry.idle.clone();
+        entries.push(entry);
+        Some(idle)
+    }
+
+    // Take a handle to the repo of the workspace, opening one if none are
+    // idle, to be given back through put once done with.
+    pub fn take(&self, git_root: &Path, workspace_id: i64) -> Result<(RepoHandles, Repository), git2::Error> {
+        let repo_dir = git_root.join(workspace_id.to_string());
+        if let Some(idle) = self.idle(workspace_id, &repo_dir) {
+            let repo = lock(&idle).pop();
+            if let Some(repo) = repo {
+                return Ok((idle, repo));
+            }
+        }
+
+        // open without holding the lock; a concurrent open for the same
+        // workspace simply shares or replaces the other entry.
+        info!("Opening repository at {:?}", repo_dir);
+        let repo = Repository::open_bare(&repo_dir)?;
+        if let Some(idle) = self.idle(workspace_id, &repo_dir) {
+            return Ok((idle, repo));
+        }
+        let idle = RepoHandles::default();
+        let mut entries = lock(&self.entries);
+        entries.retain(|entry| entry.workspace_id != workspace_id);
+        if entries.len() >= self.capacity {
+            entries.remove(0);
+        }
+        entries.push(RepoCacheEntry {
+            workspace_id: workspace_id,
+            repo_dir: repo_dir,
+            idle: idle.clone(),
+        });
+        Ok((idle, repo))
+    }
+
+    // Give back a handle from take; it is simply dropped if the entry has
+    // since been invalidated or evicted, or already has enough idle.
+    pub fn put(&self, idle: RepoHandles, repo: Repository) {
+        let mut handles = lock(&idle);
+        if handles.len() < self.handles {
+            handles.push(repo);
+        }
+    }
+
+    pub fn invalidate(&self, workspace_id: i64) {
+        lock(&self.entries).retain(|entry| entry.workspace_id != workspace_id);
+    }
+}
+
+// Run f with a cached handle to the repository for the workspace, opening
+// one if needed.
+pub fn with_repo<T>(
+    git_root: &Path,
+    workspace_id: i64,
+    f: impl FnOnce(&Repository) -> Result<T, PmrError>,
+) -> Result<T, PmrError> {
+    let (idle, repo) = REPO_CACHE.take(git_root, workspace_id)?;
+    let result = f(&repo);
+    REPO_CACHE.put(idle, repo);
+    result
+}
+
+// Drop the cached repository for the workspace, to be called whenever the
+// underlying repository was changed (e.g. synced) by some other handle.
+pub fn invalidate_repo(workspace_id: i64) {
+    REPO_CACHE.invalidate(workspace_id);
+}
+
+struct TreeListingCacheEntry {
+    workspace_id: i64,
+    commit_id: String,
+    path: String,
+    listing: Arc<Vec<TreeListingEntry>>,
+}
+
+// A bounded LRU cache of recursive tree listings, bounded by the total
+// number of entries in them. The listing of a path at a given commit never
+// changes, so entries are never invalidated.
+pub struct TreeListingCache {
+    capacity: usize,
+    entries: Mutex<Vec<TreeListingCacheEntry>>,
+}
+
+impl TreeListingCache {
+    pub const fn new(capacity: usize) -> TreeListingCache {
+        TreeListingCache {
+            capacity: capacity,
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Vec<TreeListingCacheEntry>> {
+        lock(&self.entries)
+    }
+
+    pub fn get(&self, workspace_id: i64, commit_id: &str, path: &str) -> Option<Arc<Vec<TreeListingEntry>>> {
+        let mut entries = self.entries();
+        let pos = entries.iter().position(|entry| {
+            entry.workspace_id == workspace_id && entry.commit_id == commit_id && entry.path == path
+        })?;
+        let entry = entries.remove(pos);
+        let listing = entry.listing.clone();
+        entries.push(entry);
+        Some(listing)
+    }
+
+    pub fn insert(&self, workspace_id: i64, commit_id: &str, path: &str, listing: Arc<Vec<TreeListingEntry>>) {
+        let mut entries = self.entries();
+        entries.retain(|entry| {
+            !(entry.workspace_id == workspace_id && entry.commit_id == commit_id && entry.path == path)
+        });
+        // a listing that could never fit is not worth evicting everything for
+        if listing.len() > self.capacity {
+            return;
+        }
+        let mut size: usize = entries.iter().map(|entry| entry.listing.len()).sum();
+        while size + listing.len() > self.capacity {
+            size -= entries.remove(0).listing.len();
+        }
+        entries.push(TreeListingCacheEntry {
+            workspace_id: workspace_id,
+            commit_id: commit_id.to_string(),
+            path: path.to_string(),
+            listing: listing,
+        });
+    }
+}
+
+pub fn cached_tree_listing(workspace_id: i64, commit_id: &str, path: &str) -> Option<Arc<Vec<TreeListingEntry>>> {
+    TREE_LISTING_CACHE.get(workspace_id, commit_id, path)
+}
+
+pub fn cache_tree_listing(workspace_id: i64, commit_id: &str, path: &str, listing: Arc<Vec<TreeListingEntry>>) {
+    TREE_LISTING_CACHE.insert(workspace_id, commit_id, path, listing);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(len: usize) -> Arc<Vec<TreeListingEntry>> {
+        Arc::new((0..len).map(|i| TreeListingEntry {
+            path: i.to_string(),
+            filemode: "100644".to_string(),
+            kind: "blob".to_string(),
+            id: "0".repeat(40),
+            size: None,
+            last_commit: None,
+        }).collect())
+    }
+
+    fn cached(cache: &RepoCache) -> Vec<i64> {
+        lock(&cache.entries).iter().map(|entry| entry.workspace_id).collect()
+    }
+
+    #[test]
+    fn test_repo_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        for id in 1..=3 {
+            Repository::init_bare(dir.path().join(id.to_string())).unwrap();
+        }
+        let cache = RepoCache::new(2, 2);
+        let (idle, repo) = cache.take(dir.path(), 1).unwrap();
+        cache.put(idle, repo);
+        let (idle, repo) = cache.take(dir.path(), 2).unwrap();
+        cache.put(idle, repo);
+        assert_eq!(cached(&cache), vec![1, 2]);
+
+        // handles are reused once given back, and more opened while in use
+        let (idle, a) = cache.take(dir.path(), 1).unwrap();
+        assert_eq!(lock(&idle).len(), 0);
+        let (_, b) = cache.take(dir.path(), 1).unwrap();
+        let (_, c) = cache.take(dir.path(), 1).unwrap();
+        cache.put(idle.clone(), a);
+        cache.put(idle.clone(), b);
+        cache.put(idle.clone(), c);
+        assert_eq!(lock(&idle).len(), 2);
+
+        // 1 was used last, so 2 is the one evicted
+        let (idle, repo) = cache.take(dir.path(), 3).unwrap();
+        cache.put(idle, repo);
+        assert_eq!(cached(&cache), vec![1, 3]);
+
+        // a handle given back after invalidation is not kept
+        let (idle, repo) = cache.take(dir.path(), 3).unwrap();
+        cache.invalidate(3);
+        assert_eq!(cached(&cache), vec![1]);
+        cache.put(idle, repo);
+        let (idle, _) = cache.take(dir.path(), 3).unwrap();
+        assert_eq!(lock(&idle).len(), 0);
+
+        // the same id under another git_root is another repo
+        let other = tempfile::tempdir().unwrap();
+        Repository::init_bare(other.path().join("1")).unwrap();
+        let (idle, repo) = cache.take(other.path(), 1).unwrap();
+        assert_eq!(repo.path(), other.path().join("1").canonicalize().unwrap().join(""));
+        assert_eq!(lock(&idle).len(), 0);
+        assert!(cache.take(dir.path(), 4).is_err());
+    }
+
+    #[test]
+    fn test_tree_listing_cache_bound() {
+        let cache = TreeListingCache::new(10);
+        cache.insert(1, "a", "", listing(4));
+        cache.insert(1, "b", "", listing(4));
+        assert!(cache.get(1, "a", "").is_some());
+        // evicts b, the least recently used, to make room
+        cache.insert(1, "c", "", listing(4));
+        assert!(cache.get(1, "a", "").is_some());
+        assert!(cache.get(1, "b", "").is_none());
+        assert!(cache.get(1, "c", "").is_some());
+        // too large to ever fit, so nothing else is evicted for it
+        cache.insert(1, "d", "", listing(11));
+        assert!(cache.get(1, "d", "").is_none());
+        assert!(cache.get(1, "a", "").is_some());
+    }
+}
@@ -0,0 +1,241 @@
+use std::path::Path;
+
+// Only the start of a blob is looked at to tell what it is.
+const SNIFF_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CellmlVersion {
+    V1_0,
+    V1_1,
+    V2_0,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FileKind {
+    Cellml(CellmlVersion),
+    Sedml,
+    Sbml,
+    Rdf,
+    Xml,
+    Image,
+    Pdf,
+    Text,
+    Binary,
+}
+
+impl FileKind {
+    // Every name given by name, in the order of the variants.
+    pub const NAMES: &'static [&'static str] = &[
+        "cellml", "sedml", "sbml", "rdf", "xml", "image", "pdf", "text", "binary",
+    ];
+
+    // The name of the kind regardless of version, e.g. to filter on.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileKind::Cellml(_) => "cellml",
+            FileKind::Sedml => "sedml",
+            FileKind::Sbml => "sbml",
+            FileKind::Rdf => "rdf",
+            FileKind::Xml => "xml",
+            FileKind::Image => "image",
+            FileKind::Pdf => "pdf",
+            FileKind::Text => "text",
+            FileKind::Binary => "binary",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileType {
+    pub kind: FileKind,
+    pub mime_type: &'static str,
+}
+
+impl FileType {
+    fn new(kind: FileKind, mime_type: &'static str) -> FileType {
+        FileType {
+            kind: kind,
+            mime_type: mime_type,
+        }
+    }
+}
+
+// Classify a file by its content where that is conclusive, i.e. for the
+// formats with a magic number and for XML with a known namespace on its
+// root element, and by the extension of its name otherwise.
+pub fn classify(name: &str, content: &[u8]) -> FileType {
+    let head = &content[..content.len().min(SNIFF_SIZE)];
+    if let Some(file_type) = sniff_magic(head) {
+        return file_type;
+    }
+    let ext = Path::new(name).extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase)
+        .unwrap_or_default();
+    // a NUL byte is as good a sign of binary content as any, and is what
+    // git looks for too
+    let binary = head.contains(&0);
+    if !binary {
+        // the head may well end part way through a character
+        if let Some(ns) = root_namespace(&String::from_utf8_lossy(head)) {
+            if let Some(file_type) = classify_namespace(ns) {
+                return file_type;
+            }
+        }
+    }
+    match classify_extension(&ext) {
+        Some(file_type) => file_type,
+        None if binary => FileType::new(FileKind::Binary, "application/octet-stream"),
+        None => FileType::new(FileKind::Text, "text/plain"),
+    }
+}
+
+fn sniff_magic(head: &[u8]) -> Option<FileType> {
+    if head.starts_with(b"%PDF-") {
+        Some(FileType::new(FileKind::Pdf, "application/pdf"))
+    }
+    else if head.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some(FileType::new(FileKind::Image, "image/png"))
+    }
+    else if head.starts_with(b"\xff\xd8\xff") {
+        Some(FileType::new(FileKind::Image, "image/jpeg"))
+    }
+    else if head.starts_with(b"GIF87a") || head.starts_with(b"GIF89a") {
+        Some(FileType::new(FileKind::Image, "image/gif"))
+    }
+    else {
+        None
+    }
+}
+
+fn classify_namespace(ns: &str) -> Option<FileType> {
+    let file_type = match ns {
+        "http://www.cellml.org/cellml/1.0#" =>
+            FileType::new(FileKind::Cellml(CellmlVersion::V1_0), "application/cellml+xml"),
+        "http://www.cellml.org/cellml/1.1#" =>
+            FileType::new(FileKind::Cellml(CellmlVersion::V1_1), "application/cellml+xml"),
+        "http://www.cellml.org/cellml/2.0#" =>
+            FileType::new(FileKind::Cellml(CellmlVersion::V2_0), "application/cellml+xml"),
+        "http://www.w3.org/1999/02/22-rdf-syntax-ns#" =>
+            FileType::new(FileKind::Rdf, "application/rdf+xml"),
+        "http://www.w3.org/2000/svg" =>
+            FileType::new(FileKind::Image, "image/svg+xml"),
+        ns if ns.starts_with("http://sed-ml.org/") =>
+            FileType::new(FileKind::Sedml, "application/sedml+xml"),
+        ns if ns.starts_with("http://www.sbml.org/sbml/") =>
+            FileType::new(FileKind::Sbml, "application/sbml+xml"),
+        _ => return None,
+    };
+    Some(file_type)
+}
+
+fn classify_extension(ext: &str) -> Option<FileType> {
+    let file_type = match ext {
+        "svg" => FileType::new(FileKind::Image, "image/svg+xml"),
+        "png" => FileType::new(FileKind::Image, "image/png"),
+        "jpg" | "jpeg" => FileType::new(FileKind::Image, "image/jpeg"),
+        "gif" => FileType::new(FileKind::Image, "image/gif"),
+        "pdf" => FileType::new(FileKind::Pdf, "application/pdf"),
+        // without the expected namespace these can only be taken as XML
+        "xml" | "xsl" | "cellml" | "sedml" | "sbml" | "rdf" => FileType::new(FileKind::Xml, "application/xml"),
+        "html" | "htm" => FileType::new(FileKind::Text, "text/html"),
+        "css" => FileType::new(FileKind::Text, "text/css"),
+        "js" => FileType::new(FileKind::Text, "text/javascript"),
+        "json" => FileType::new(FileKind::Text, "application/json"),
+        "csv" => FileType::new(FileKind::Text, "text/csv"),
+        "md" | "rst" | "txt" => FileType::new(FileKind::Text, "text/plain"),
+        "zip" => FileType::new(FileKind::Binary, "application/zip"),
+        _ => return None,
+    };
+    Some(file_type)
+}
+
+// The namespace of the root element of an XML document, found by scanning
+// rather than parsing as only the start of the document is at hand; None
+// if the text doesn't look like XML or the root has no namespace.
+fn root_namespace(text: &str) -> Option<&str> {
+    let mut rest = text.trim_start_matches('\u{feff}').trim_start();
+    // skip the declaration, processing instructions, comments and doctype
+    loop {
+        if !rest.starts_with('<') {
+            return None;
+        }
+        let end = if rest.starts_with("<!--") {
+            rest.find("-->")? + 3
+        }
+        else if rest.starts_with("<?") || rest.starts_with("<!") {
+            rest.find('>')? + 1
+        }
+        else {
+            break;
+        };
+        rest = rest[end..].trim_start();
+    }
+
+    let tag = &rest[1..rest.find('>')?];
+    let name = tag.split(|c: char| c.is_whitespace() || c == '/').next()?;
+    let attr = match name.split_once(':') {
+        Some((prefix, _)) => format!("xmlns:{}", prefix),
+        None => "xmlns".to_string(),
+    };
+    let mut attrs = tag[name.len()..].trim_start();
+    while let Some(eq) = attrs.find('=') {
+        let key = attrs[..eq].trim();
+        let value = attrs[eq + 1..].trim_start();
+        let quote = value.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &value[1..];
+        let close = value.find(quote)?;
+        if key == attr {
+            return Some(&value[..close]);
+        }
+        attrs = value[close + 1..].trim_start();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let cellml = br#"<?xml version="1.0"?>
+<!-- a comment -->
+<model xmlns="http://www.cellml.org/cellml/1.1#" name="m"/>"#;
+        assert_eq!(classify("m.xml", cellml).kind, FileKind::Cellml(CellmlVersion::V1_1));
+        assert_eq!(classify("m.cellml", b"<model name='m'/>").kind, FileKind::Xml);
+        assert_eq!(classify("s.sedml", br#"<sedML xmlns="http://sed-ml.org/sed-ml/level1/version3"/>"#).kind, FileKind::Sedml);
+        let rdf = br#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#"/>"#;
+        assert_eq!(classify("meta", rdf).kind, FileKind::Rdf);
+        let svg = classify("drawing", br#"<svg xmlns="http://www.w3.org/2000/svg"/>"#);
+        assert_eq!((svg.kind, svg.mime_type), (FileKind::Image, "image/svg+xml"));
+        // magic numbers win over the extension
+        assert_eq!(classify("doc.txt", b"%PDF-1.4\n").kind, FileKind::Pdf);
+        assert_eq!(classify("img", b"\x89PNG\r\n\x1a\n\0\0").mime_type, "image/png");
+        assert_eq!(classify("README", b"plain text").kind, FileKind::Text);
+        assert_eq!(classify("data.json", b"{}").mime_type, "application/json");
+        assert_eq!(classify("blob.dat", b"\0\x01\x02").kind, FileKind::Binary);
+        assert_eq!(classify("DATA.ZIP", b"PK\x03\x04\0").mime_type, "application/zip");
+    }
+
+    #[test]
+    fn test_root_namespace() {
+        assert_eq!(root_namespace(r#"<a xmlns="urn:a"/>"#), Some("urn:a"));
+        assert_eq!(root_namespace("\u{feff}<?xml version='1.0'?><!DOCTYPE a><a xmlns='urn:a'>"), Some("urn:a"));
+        assert_eq!(root_namespace(r#"<x:a xmlns="urn:a" xmlns:x="urn:x">"#), Some("urn:x"));
+        assert_eq!(root_namespace(r#"<a id="xmlns=urn:b" xmlns = "urn:a">"#), Some("urn:a"));
+        assert_eq!(root_namespace(r#"<a xmlns:x="urn:x"/>"#), None);
+        assert_eq!(root_namespace("<a"), None);
+        assert_eq!(root_namespace("not xml"), None);
+    }
+
+    #[test]
+    fn test_file_kind_names() {
+        let kinds = [
+            FileKind::Cellml(CellmlVersion::V1_0), FileKind::Sedml, FileKind::Sbml, FileKind::Rdf,
+            FileKind::Xml, FileKind::Image, FileKind::Pdf, FileKind::Text, FileKind::Binary,
+        ];
+        let names = kinds.iter().map(FileKind::name).collect::<Vec<_>>();
+        assert_eq!(names, FileKind::NAMES);
+    }
+}
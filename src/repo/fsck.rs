@@ -0,0 +1,203 @@
+use async_std::task;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use git2::{Oid, Repository};
+
+use crate::error::PmrError;
+use crate::model::backend::SqliteBackend;
+use crate::model::user::Caller;
+use crate::model::workspace::{
+    WorkspaceBackend,
+    WorkspaceRecord,
+};
+use crate::model::workspace_tag::WorkspaceTagBackend;
+use crate::model::workspace_url::WorkspaceUrlError;
+use crate::repo::cache::{invalidate_repo, with_repo};
+use crate::repo::git::{
+    GitPmrAccessor,
+    LOCK_DIR,
+    QUARANTINE_DIR,
+    git_sync_workspace,
+    index_tags,
+    spawn_git,
+};
+
+// A disagreement between the database and what is under the git root.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FsckIssue {
+    // registered, but nothing has been cloned for it
+    MissingRepo { workspace_id: i64 },
+    // a repo directory with no workspace registered for it
+    OrphanRepo { path: PathBuf },
+    // something other than a bare repo where the repo should be
+    InvalidRepo { workspace_id: i64, path: PathBuf, error: String },
+    // an indexed tag whose object the repo no longer has
+    DanglingTag { workspace_id: i64, name: String, commit_id: String },
+    // a tag in the repo that was never indexed
+    UnindexedTag { workspace_id: i64, name: String, commit_id: String },
+    // registered before url keys were kept
+    MissingUrlKey { workspace_id: i64 },
+    // the same remote registered more than once, found when filling in
+    // a missing url key
+    DuplicateUrl { workspace_id: i64, other_workspace_id: i64 },
+    // a url that no key can be made from, found the same way
+    InvalidUrl { workspace_id: i64, error: String },
+}
+
+#[derive(Debug, Serialize)]
+pub struct FsckFinding {
+    #[serde(flatten)]
+    pub issue: FsckIssue,
+    // whether the issue was fixed; only the safe cases are ever attempted
+    pub fixed: bool,
+}
+
+// Check every workspace against its repo, and the git root for repos
+// nothing refers to. With fix, missing repos are cloned again, tags
+// missing from the index are indexed and missing url keys filled in.
+pub async fn fsck(
+    backend: &SqliteBackend, caller: &Caller, git_root: &Path, fix: bool
+) -> Result<Vec<FsckFinding>, PmrError> {
+    if caller != &Caller::System {
+        return Err(PmrError::PermissionDenied(format!("{:?} may not check the repositories", caller)));
+    }
+    let workspaces = WorkspaceBackend::list_workspaces(backend, &Caller::System).await?;
+    let mut findings = Vec::new();
+    for workspace in workspaces.iter() {
+        findings.extend(fsck_workspace(backend, git_root, workspace, fix).await?);
+    }
+
+    let known: HashSet<String> = workspaces.iter().map(|workspace| workspace.id.to_string()).collect();
+    let entries = {
+        let git_root = git_root.to_path_buf();
+        task::spawn_blocking(move || -> std::io::Result<Vec<(String, PathBuf)>> {
+            std::fs::read_dir(&git_root)?
+                .map(|entry| entry.map(|entry| (entry.file_name().to_string_lossy().into_owned(), entry.path())))
+                .collect()
+        }).await?
+    };
+    for (name, path) in entries {
+        if name == QUARANTINE_DIR || name == LOCK_DIR || known.contains(&name) {
+            continue;
+        }
+        findings.push(FsckFinding {
+            issue: FsckIssue::OrphanRepo { path: path },
+            fixed: false,
+        });
+    }
+    Ok(findings)
+}
+
+async fn fsck_workspace(
+    backend: &SqliteBackend, git_root: &Path, workspace: &WorkspaceRecord, fix: bool
+) -> Result<Vec<FsckFinding>, PmrError> {
+    let workspace_id = workspace.id;
+    let repo_dir = git_root.join(workspace_id.to_string());
+    let mut findings = Vec::new();
+
+    if workspace.url_key.is_none() {
+        let mut fixed = false;
+        if fix {
+            match WorkspaceBackend::update_workspace_url_key(backend, &Caller::System, workspace_id).await {
+                Ok(_) => fixed = true,
+                Err(PmrError::Url(WorkspaceUrlError::Conflict(other_workspace_id))) => {
+                    findings.push(FsckFinding {
+                        issue: FsckIssue::DuplicateUrl { workspace_id, other_workspace_id },
+                        fixed: false,
+                    });
+                },
+                Err(PmrError::Url(e)) => {
+                    findings.push(FsckFinding {
+                        issue: FsckIssue::InvalidUrl { workspace_id, error: e.to_string() },
+                        fixed: false,
+                    });
+                },
+                Err(e) => return Err(e),
+            }
+        }
+        findings.push(FsckFinding {
+            issue: FsckIssue::MissingUrlKey { workspace_id },
+            fixed: fixed,
+        });
+    }
+
+    if !async_std::path::Path::new(&repo_dir).exists().await {
+        // archived workspaces are never synced, so cannot be cloned again
+        let fixed = fix && workspace.archived.is_none() && git_sync_workspace(
+            &accessor(backend, git_root, workspace)).await.is_ok();
+        findings.push(FsckFinding {
+            issue: FsckIssue::MissingRepo { workspace_id },
+            fixed: fixed,
+        });
+        return Ok(findings);
+    }
+
+    // open directly rather than through the cache, which would hide a
+    // repo that has since been replaced on disk
+    invalidate_repo(workspace_id);
+    let opened = {
+        let repo_dir = repo_dir.clone();
+        spawn_git(move || Repository::open_bare(&repo_dir).map(|_| ())).await
+    };
+    if let Err(e) = opened {
+        findings.push(FsckFinding {
+            issue: FsckIssue::InvalidRepo { workspace_id, path: repo_dir, error: e.message().to_string() },
+            fixed: false,
+        });
+        return Ok(findings);
+    }
+
+    let indexed: HashMap<String, String> = WorkspaceTagBackend::get_workspace_tags(
+        backend, &Caller::System, workspace_id).await?
+        .into_iter().map(|rec| (rec.name, rec.commit_id)).collect();
+    let (tags, dangling) = {
+        let git_root = git_root.to_path_buf();
+        let indexed = indexed.clone();
+        spawn_git(move || with_repo(&git_root, workspace_id, |repo| {
+            let mut tags = Vec::new();
+            repo.tag_foreach(|oid, name| {
+                tags.push((String::from_utf8_lossy(name).into_owned(), format!("{}", oid)));
+                true
+            })?;
+            let odb = repo.odb()?;
+            let dangling: Vec<_> = indexed.into_iter()
+                .filter(|(_, commit_id)| !Oid::from_str(commit_id).map_or(false, |oid| odb.exists(oid)))
+                .collect();
+            Ok((tags, dangling))
+        })).await?
+    };
+
+    let unindexed: Vec<_> = tags.into_iter()
+        .filter(|(name, _)| !indexed.contains_key(name))
+        .collect();
+    let fixed = fix && !unindexed.is_empty() && match index_tags(&accessor(backend, git_root, workspace)).await {
+        Ok(summary) => summary.conflicting.is_empty(),
+        Err(_) => false,
+    };
+    for (name, commit_id) in unindexed {
+        findings.push(FsckFinding {
+            issue: FsckIssue::UnindexedTag { workspace_id, name, commit_id },
+            fixed: fixed,
+        });
+    }
+    // the index is left alone, as the repo may only be behind the remote
+    for (name, commit_id) in dangling {
+        findings.push(FsckFinding {
+            issue: FsckIssue::DanglingTag { workspace_id, name, commit_id },
+            fixed: false,
+        });
+    }
+    Ok(findings)
+}
+
+fn accessor(backend: &SqliteBackend, git_root: &Path, workspace: &WorkspaceRecord) -> GitPmrAccessor {
+    GitPmrAccessor::new(
+        SqliteBackend::new((*backend.pool).clone()),
+        git_root.to_path_buf(),
+        workspace.clone(),
+        Caller::System,
+    )
+}
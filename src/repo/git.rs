@@ -1,21 +1,52 @@
-use anyhow::bail;
-use futures::stream::StreamExt;
-use futures::stream::futures_unordered::FuturesUnordered;
-use std::io::Write;
-use git2::{Repository, Blob, Commit, Object, ObjectType, Tree};
+use async_lock::Semaphore;
+use async_std::task;
+use chrono::TimeZone;
+use fs2::FileExt;
+use std::io::{Read, Write};
+use git2::{Repository, Blob, Commit, Object, ObjectType, Tree, TreeWalkMode, TreeWalkResult};
 use sqlx::sqlite::SqlitePool;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use crate::cellml::imports::{extract_imports, resolve_href};
+use crate::cellml::rdf::{Triple, extract_keywords, extract_triples};
+use crate::error::PmrError;
 use crate::model::backend::SqliteBackend;
+use crate::model::search::SearchBackend;
+use crate::model::user::Caller;
+use crate::repo::cache::{
+    cache_tree_listing,
+    cached_tree_listing,
+    invalidate_repo,
+    with_repo,
+};
+use crate::repo::filetype::{FileKind, FileType, classify};
 use crate::model::workspace::{
     WorkspaceBackend,
+    WorkspaceDeleteSummary,
     WorkspaceRecord,
 };
+use crate::model::workspace_acl::{
+    WorkspaceAclBackend,
+    WorkspaceRole,
+};
+use crate::model::workspace_keyword::WorkspaceKeywordBackend;
+use crate::model::workspace_maintenance::WorkspaceMaintenanceBackend;
+use crate::model::workspace_url::WorkspaceUrlError;
 use crate::model::workspace_sync::{
     WorkspaceSyncBackend,
     WorkspaceSyncStatus,
 };
-use crate::model::workspace_tag::WorkspaceTagBackend;
+use crate::model::workspace_import::{
+    WorkspaceImport,
+    WorkspaceImportBackend,
+};
+use crate::model::workspace_metadata::WorkspaceMetadataBackend;
+use crate::model::workspace_tag::{
+    WorkspaceTagBackend,
+    WorkspaceTagIndexSummary,
+};
 
 // TODO encapsulate the standard set of argument as a struct?
 pub struct GitPmrAccessor {
@@ -24,20 +55,36 @@ pub struct GitPmrAccessor {
     backend: SqliteBackend,
     git_root: PathBuf,
     workspace: WorkspaceRecord,
+    caller: Caller,
 }
 
 impl GitPmrAccessor {
     // TODO have constructor that takes a workspace_id?
     // not sure how to deal with async
-    pub fn new(backend: SqliteBackend, git_root: PathBuf, workspace: WorkspaceRecord) -> GitPmrAccessor {
+    pub fn new(
+        backend: SqliteBackend, git_root: PathBuf, workspace: WorkspaceRecord, caller: Caller
+    ) -> GitPmrAccessor {
         // TODO the SqliteBackend here is moved?
         // figure out if we can make this a reference?
         GitPmrAccessor {
             backend: backend,
             git_root: git_root,
             workspace: workspace,
+            caller: caller,
         }
     }
+
+    pub fn backend(&self) -> &SqliteBackend {
+        &self.backend
+    }
+
+    pub fn workspace(&self) -> &WorkspaceRecord {
+        &self.workspace
+    }
+
+    pub fn caller(&self) -> &Caller {
+        &self.caller
+    }
 }
 
 pub struct GitResultSet<'git_result_set> {
@@ -55,12 +102,53 @@ pub struct TreeEntryInfo {
     name: String,
 }
 
+// The commit that last changed an entry, as of the commit listed.
+#[derive(Debug, Clone)]
+pub struct LastCommit {
+    pub commit_id: String,
+    pub time: i64,
+    pub summary: String,
+}
+
+// An entry anywhere below the listed tree; the path is from the root of
+// the repo and the size is only known for blobs.
+#[derive(Debug, Clone)]
+pub struct TreeListingEntry {
+    pub path: String,
+    pub filemode: String,
+    pub kind: String,
+    pub id: String,
+    pub size: Option<u64>,
+    pub last_commit: Option<LastCommit>,
+}
+
+impl std::fmt::Display for TreeListingEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} - {} - {} - {} - {}",
+            &self.filemode,
+            &self.kind,
+            match self.size {
+                Some(size) => size.to_string(),
+                None => "-".to_string(),
+            },
+            &self.path,
+            match &self.last_commit {
+                Some(last_commit) => format!("{} {}", &last_commit.commit_id[..7], &last_commit.summary),
+                None => "<unknown>".to_string(),
+            },
+        )
+    }
+}
+
 // For blob?
 #[derive(Debug)]
 pub enum ObjectInfo {
     FileInfo {
         size: u64,
         binary: bool,
+        file_type: FileType,
     },
     TreeInfo {
         filecount: u64,
@@ -73,133 +161,1180 @@ pub enum ObjectInfo {
     },
 }
 
+// Blobs larger than this are not added to the search index.
+const MAX_SEARCH_BLOB_SIZE: usize = 1 << 20;
+// Blobs larger than this are not parsed for metadata.
+const MAX_METADATA_BLOB_SIZE: usize = 16 << 20;
 
-pub async fn git_sync_workspace(git_pmr_accessor: &GitPmrAccessor) -> anyhow::Result<()> {
-    let repo_dir = git_pmr_accessor.git_root.join(git_pmr_accessor.workspace.id.to_string());
-    let repo_check = Repository::open_bare(&repo_dir);
+// Upper bound on the number of libgit2 operations that may be running on
+// the blocking thread pool at any one time.
+const MAX_GIT_THREADS: usize = 8;
+static GIT_THREADS: Semaphore = Semaphore::new(MAX_GIT_THREADS);
+
+// libgit2 calls (clone, fetch, revparse, tree walks) are all blocking, so
+// they must be run away from the async executor.
+pub(crate) async fn spawn_git<F, T>(f: F) -> T
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let _guard = GIT_THREADS.acquire().await;
+    task::spawn_blocking(f).await
+}
+
+// Held while anything writes to the repo of a workspace, so that sync,
+// maintenance and removal never run at once, even from other processes;
+// the lock is released when this is dropped.
+pub struct WorkspaceLock {
+    _file: std::fs::File,
+}
+
+pub async fn lock_workspace(git_root: &Path, workspace_id: i64) -> Result<WorkspaceLock, PmrError> {
+    let lock_dir = git_root.join(LOCK_DIR);
+    // waiting on the lock is not git work, so it doesn't take GIT_THREADS
+    let file = task::spawn_blocking(move || -> std::io::Result<std::fs::File> {
+        std::fs::create_dir_all(&lock_dir)?;
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(lock_dir.join(workspace_id.to_string()))?;
+        file.lock_exclusive()?;
+        Ok(file)
+    }).await?;
+    Ok(WorkspaceLock { _file: file })
+}
+
+pub async fn git_sync_workspace(git_pmr_accessor: &GitPmrAccessor) -> Result<(), PmrError> {
+    let workspace_id = git_pmr_accessor.workspace.id;
+    let repo_dir = git_pmr_accessor.git_root.join(workspace_id.to_string());
+    let url = git_pmr_accessor.workspace.url.clone();
+    if git_pmr_accessor.workspace.archived.is_some() {
+        return Err(PmrError::SyncConflict(format!("workspace {} is archived", workspace_id)));
+    }
 
     info!("Syncing local {:?} with remote <{}>...", repo_dir, &git_pmr_accessor.workspace.url);
-    let sync_id = WorkspaceSyncBackend::begin_sync(&git_pmr_accessor.backend, git_pmr_accessor.workspace.id).await?;
-    match repo_check {
+    // locked first, so that a sync is never left running for want of it
+    let lock = lock_workspace(&git_pmr_accessor.git_root, workspace_id).await?;
+    let sync_id = WorkspaceSyncBackend::begin_sync(
+        &git_pmr_accessor.backend, &git_pmr_accessor.caller, workspace_id).await?;
+    let result = spawn_git(move || sync_repo(&repo_dir, &url)).await;
+    // any cached handle may no longer reflect what is on disk
+    invalidate_repo(workspace_id);
+    drop(lock);
+    match result {
+        Ok(_) => (),
+        Err(msg) => WorkspaceSyncBackend::fail_sync(
+            &git_pmr_accessor.backend, &git_pmr_accessor.caller, sync_id, msg).await?,
+    };
+
+    WorkspaceSyncBackend::complete_sync(
+        &git_pmr_accessor.backend, &git_pmr_accessor.caller, sync_id, WorkspaceSyncStatus::Completed).await?;
+    index_tags(&git_pmr_accessor).await?;
+    // the sync itself is done; what is indexed from the content can be
+    // redone later and must not fail it
+    if let Err(e) = index_metadata(&git_pmr_accessor, None).await {
+        warn!("failed to index metadata for workspace {}: {}", workspace_id, e);
+    }
+    if let Err(e) = index_imports(&git_pmr_accessor, None).await {
+        warn!("failed to index imports for workspace {}: {}", workspace_id, e);
+    }
+
+    Ok(())
+}
+
+// Check that there is a repo at the url by connecting to it, which has it
+// advertise its refs as with git ls-remote, without fetching anything.
+// The refs are not listed as git2 cannot list none, i.e. for an empty repo.
+pub async fn probe_url(url: &str) -> Result<(), WorkspaceUrlError> {
+    let url = url.trim().to_string();
+    spawn_git(move || {
+        let mut remote = git2::Remote::create_detached(&url)?;
+        remote.connect(git2::Direction::Fetch)?;
+        Ok(())
+    }).await.map_err(|e: git2::Error| WorkspaceUrlError::Unreachable(e.message().to_string()))
+}
+
+// Blocking part of the sync; the error is the message to be recorded
+// against the failed sync.
+fn sync_repo(repo_dir: &Path, url: &str) -> Result<(), String> {
+    match Repository::open_bare(repo_dir) {
         Ok(repo) => {
             info!("Found existing repo at {:?}, synchronizing...", repo_dir);
-            let mut remote = repo.find_remote("origin")?;
-            match remote.fetch(&[] as &[&str], None, None) {
+            match repo.find_remote("origin")
+                .and_then(|mut remote| remote.fetch(&[] as &[&str], None, None))
+            {
                 Ok(_) => info!("Repository synchronized"),
-                Err(e) => WorkspaceSyncBackend::fail_sync(&git_pmr_accessor.backend, sync_id, format!("Failed to synchronize: {}", e)).await?,
+                Err(e) => return Err(format!("Failed to synchronize: {}", e)),
             };
         },
-        Err(ref e) if e.class() == git2::ErrorClass::Repository => WorkspaceSyncBackend::fail_sync(
-            &git_pmr_accessor.backend, sync_id, format!(
-                "Invalid data at local {:?} - expected bare repo", repo_dir)).await?,
+        Err(ref e) if e.class() == git2::ErrorClass::Repository => return Err(format!(
+            "Invalid data at local {:?} - expected bare repo", repo_dir)),
         Err(_) => {
             info!("Cloning new repository at {:?}...", repo_dir);
             let mut builder = git2::build::RepoBuilder::new();
             builder.bare(true);
-            match builder.clone(&git_pmr_accessor.workspace.url, &repo_dir) {
+            match builder.clone(url, repo_dir) {
                 Ok(_) => info!("Repository cloned"),
-                Err(e) => WorkspaceSyncBackend::fail_sync(&git_pmr_accessor.backend, sync_id, format!("Failed to clone: {}", e)).await?,
+                Err(e) => return Err(format!("Failed to clone: {}", e)),
             };
         }
     }
+    Ok(())
+}
 
-    WorkspaceSyncBackend::complete_sync(&git_pmr_accessor.backend, sync_id, WorkspaceSyncStatus::Completed).await?;
-    index_tags(&git_pmr_accessor).await?;
+// Repack the repo and prune unreachable objects left behind by fetches,
+// recording the size on disk before and after. Returns the new size.
+pub async fn git_maintain_workspace(git_pmr_accessor: &GitPmrAccessor) -> Result<u64, PmrError> {
+    let workspace_id = git_pmr_accessor.workspace.id;
+    let repo_dir = git_pmr_accessor.git_root.join(workspace_id.to_string());
+    WorkspaceAclBackend::check_workspace_permission(
+        &git_pmr_accessor.backend, &git_pmr_accessor.caller, workspace_id, WorkspaceRole::Editor).await?;
 
-    Ok(())
+    let start = chrono::Utc::now().timestamp();
+    let lock = lock_workspace(&git_pmr_accessor.git_root, workspace_id).await?;
+    info!("Repacking repository at {:?}...", repo_dir);
+    let (size_before, size_after) = spawn_git(move || maintain_repo(&repo_dir)).await?;
+    invalidate_repo(workspace_id);
+    drop(lock);
+    info!("Repository at {} bytes, was {} bytes", size_after, size_before);
+
+    WorkspaceMaintenanceBackend::record_maintenance(
+        &git_pmr_accessor.backend, &git_pmr_accessor.caller, workspace_id,
+        start, size_before as i64, size_after as i64).await?;
+    Ok(size_after)
+}
+
+// libgit2 has no equivalent of gc, so this is left to git itself; with
+// the workspace locked nothing can be referring to unreachable objects,
+// so they are pruned without the usual grace period.
+fn maintain_repo(repo_dir: &Path) -> Result<(u64, u64), PmrError> {
+    Repository::open_bare(repo_dir)?;
+    let size_before = dir_size(repo_dir)?;
+    let output = std::process::Command::new("git")
+        .arg("--git-dir")
+        .arg(repo_dir)
+        .args(&["gc", "--quiet", "--prune=now"])
+        .output()?;
+    if !output.status.success() {
+        return Err(PmrError::MaintenanceFailed(format!(
+            "git gc failed: {}", String::from_utf8_lossy(&output.stderr).trim())));
+    }
+    Ok((size_before, dir_size(repo_dir)?))
+}
+
+fn dir_size(path: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() { dir_size(&entry.path())? } else { metadata.len() };
+    }
+    Ok(size)
+}
+
+// Deleted repos are moved here rather than removed when quarantined, so
+// that they may still be recovered by hand.
+pub const QUARANTINE_DIR: &str = ".quarantine";
+// Lock files for each workspace, see lock_workspace.
+pub const LOCK_DIR: &str = ".lock";
+
+// Delete the workspace along with its bare repo, which is moved under
+// QUARANTINE_DIR if quarantine is set and removed otherwise. Both happen
+// under the workspace lock, and the repo is moved aside before the delete
+// is committed so that a failed delete can put it back. Returns what was
+// deleted and where the repo was, if there was one.
+pub async fn delete_workspace_and_repo(
+    backend: &SqliteBackend, caller: &Caller, git_root: &Path, workspace_id: i64, quarantine: bool, dry_run: bool
+) -> Result<Option<(WorkspaceDeleteSummary, Option<PathBuf>)>, PmrError> {
+    let _lock = lock_workspace(git_root, workspace_id).await?;
+    let repo_dir = git_root.join(workspace_id.to_string());
+    let has_repo = async_std::path::Path::new(&repo_dir).exists().await;
+    // checks the caller and that there is a workspace before the repo is
+    // touched
+    let summary = match WorkspaceBackend::delete_workspace(backend, caller, workspace_id, true).await? {
+        Some(summary) => summary,
+        None => return Ok(None),
+    };
+    if dry_run {
+        return Ok(Some((summary, if has_repo { Some(repo_dir) } else { None })));
+    }
+
+    let moved = if has_repo {
+        let quarantine_dir = git_root.join(QUARANTINE_DIR);
+        async_std::fs::create_dir_all(&quarantine_dir).await?;
+        let target = quarantine_dir.join(format!("{}-{}", workspace_id, chrono::Utc::now().timestamp()));
+        info!("Moving repository at {:?} to {:?}", repo_dir, target);
+        async_std::fs::rename(&repo_dir, &target).await?;
+        invalidate_repo(workspace_id);
+        Some(target)
+    }
+    else {
+        None
+    };
+
+    let summary = match WorkspaceBackend::delete_workspace(backend, caller, workspace_id, false).await {
+        Ok(Some(summary)) => summary,
+        result => {
+            if let Some(target) = &moved {
+                warn!("Deleting workspace {} failed, moving repository back to {:?}", workspace_id, repo_dir);
+                async_std::fs::rename(target, &repo_dir).await?;
+            }
+            return result.map(|_| None);
+        }
+    };
+    if let Some(target) = &moved {
+        if !quarantine {
+            info!("Removing repository at {:?}", target);
+            async_std::fs::remove_dir_all(target).await?;
+        }
+    }
+    // removed while still held, as nothing is left for anyone waiting on
+    // it to work on
+    match async_std::fs::remove_file(git_root.join(LOCK_DIR).join(workspace_id.to_string())).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => (),
+    }
+    Ok(Some((summary, moved.map(|_| repo_dir))))
 }
 
-pub async fn index_tags(git_pmr_accessor: &GitPmrAccessor) -> anyhow::Result<()> {
+pub async fn index_tags(git_pmr_accessor: &GitPmrAccessor) -> Result<WorkspaceTagIndexSummary, PmrError> {
     let backend = &git_pmr_accessor.backend;
-    let git_root = &git_pmr_accessor.git_root;
+    let git_root = git_pmr_accessor.git_root.clone();
     let workspace = &git_pmr_accessor.workspace;
-    let repo_dir = git_root.join(workspace.id.to_string());
-    let repo = Repository::open_bare(repo_dir)?;
+    let workspace_id = workspace.id;
 
     // collect all the tags for processing later
-    let mut tags = Vec::new();
-    repo.tag_foreach(|oid, name| {
-        // swapped position for next part.
-        tags.push((String::from_utf8(name.into()).unwrap(), format!("{}", oid)));
-        true
+    let tags = spawn_git(move || with_repo(&git_root, workspace_id, |repo| {
+        let mut tags = Vec::new();
+        repo.tag_foreach(|oid, name| {
+            // swapped position for next part.
+            tags.push((String::from_utf8(name.into()).unwrap(), format!("{}", oid)));
+            true
+        })?;
+        Ok(tags)
+    })).await?;
+
+    let summary = WorkspaceTagBackend::index_workspace_tags(
+        backend, &git_pmr_accessor.caller, workspace.id, &tags).await?;
+    for name in &summary.inserted {
+        info!("indexed tag: {}", name);
+    }
+    for name in &summary.conflicting {
+        warn!("tagging conflict: {} already indexed with a different commit", name);
+    }
+
+    Ok(summary)
+}
+
+// Any read of the repository content must be allowed for the caller.
+async fn check_visible(git_pmr_accessor: &GitPmrAccessor) -> Result<(), PmrError> {
+    let workspace_id = git_pmr_accessor.workspace.id;
+    if !WorkspaceBackend::is_workspace_visible(
+        &git_pmr_accessor.backend, &git_pmr_accessor.caller, workspace_id).await?
+    {
+        return Err(PmrError::NotFound(format!("workspace {}", workspace_id)));
+    }
+    Ok(())
+}
+
+pub async fn get_obj_by_spec(git_pmr_accessor: &GitPmrAccessor, spec: &str) -> Result<(), PmrError> {
+    check_visible(git_pmr_accessor).await?;
+    let git_root = git_pmr_accessor.git_root.clone();
+    let workspace_id = git_pmr_accessor.workspace.id;
+    let spec = spec.to_string();
+    spawn_git(move || with_repo(&git_root, workspace_id, |repo| {
+        let obj = repo.revparse_single(&spec)
+            .map_err(|_| PmrError::InvalidRevision(spec.clone()))?;
+        info!("Found object {} {}", obj.kind().unwrap().str(), obj.id());
+        // a spec of the form rev:path names the path of the object
+        let path = spec.split_once(':').map_or("", |(_, path)| path);
+        info!("{:?}", object_to_info(&repo, &obj, path));
+        Ok(())
+    })).await
+}
+
+// Index the text blobs at the default branch for full-text search,
+// replacing whatever was indexed for the workspace before.
+pub async fn index_search_blobs(git_pmr_accessor: &GitPmrAccessor) -> Result<u64, PmrError> {
+    check_visible(git_pmr_accessor).await?;
+    let git_root = git_pmr_accessor.git_root.clone();
+    let workspace_id = git_pmr_accessor.workspace.id;
+    let (commit_id, blobs) = spawn_git(move || with_repo(&git_root, workspace_id, |repo| {
+        let commit = find_commit(repo, None)?;
+        let mut blobs = Vec::new();
+        for_each_text_blob(repo, &commit.tree()?, MAX_SEARCH_BLOB_SIZE, |path, text| {
+            blobs.push((path, text.to_string()));
+        })?;
+        Ok((format!("{}", commit.id()), blobs))
+    })).await?;
+
+    info!("Indexing {} text blobs at commit {}", blobs.len(), commit_id);
+    SearchBackend::index_workspace_blobs(
+        &git_pmr_accessor.backend, &git_pmr_accessor.caller, workspace_id, &commit_id, &blobs).await
+}
+
+// Matches are given with up to context lines either side of them; a
+// search stops once max_matches are found.
+#[derive(Debug, Clone)]
+pub struct GrepQuery {
+    pub pattern: String,
+    // match the pattern as is rather than as a regex
+    pub literal: bool,
+    pub ignore_case: bool,
+    // a directory or file to search within, rather than the whole tree
+    pub path: Option<String>,
+    // only search files of this kind, see FileKind::name
+    pub kind: Option<String>,
+    pub context: usize,
+    pub max_matches: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct GrepMatch {
+    pub path: String,
+    pub line_no: usize,
+    pub line: String,
+    pub before: Vec<String>,
+    pub after: Vec<String>,
+}
+
+impl std::fmt::Display for GrepMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}",
+            &self.path,
+            self.line_no,
+            &self.line,
+        )
+    }
+}
+
+pub struct GrepResults {
+    pub commit_id: String,
+    pub matches: Vec<GrepMatch>,
+    // whether the search stopped at max_matches
+    pub truncated: bool,
+    // the text files that were not searched, being too large or not UTF-8
+    pub skipped: Vec<String>,
+}
+
+// Search the text blobs in the tree of the commit (default branch if not
+// specified) line by line, as with git grep.
+pub async fn grep(
+    git_pmr_accessor: &GitPmrAccessor, commit_id: Option<&str>, query: &GrepQuery
+) -> Result<GrepResults, PmrError> {
+    check_visible(git_pmr_accessor).await?;
+    if let Some(kind) = query.kind.as_deref() {
+        if !FileKind::NAMES.contains(&kind) {
+            return Err(PmrError::InvalidInput(format!(
+                "unknown kind '{}', expected one of {}", kind, FileKind::NAMES.join(", "))));
+        }
+    }
+    let pattern = if query.literal { regex::escape(&query.pattern) } else { query.pattern.clone() };
+    let regex = regex::RegexBuilder::new(&pattern)
+        .case_insensitive(query.ignore_case)
+        .build()
+        .map_err(|e| PmrError::InvalidInput(format!("invalid pattern: {}", e)))?;
+    let git_root = git_pmr_accessor.git_root.clone();
+    let workspace_id = git_pmr_accessor.workspace.id;
+    let commit_id = commit_id.map(String::from);
+    let query = query.clone();
+    spawn_git(move || with_repo(&git_root, workspace_id, |repo| {
+        let commit = find_commit(repo, commit_id.as_deref())?;
+        let path = query.path.as_deref().map(|path| path.trim_matches('/')).unwrap_or("");
+        let object = match path {
+            "" => commit.tree()?.into_object(),
+            path => commit.tree()?.get_path(Path::new(path))
+                .map_err(|_| PmrError::PathNotFound(path.to_string()))?
+                .to_object(repo)?,
+        };
+        let mut results = GrepResults {
+            commit_id: format!("{}", commit.id()),
+            matches: Vec::new(),
+            truncated: false,
+            skipped: Vec::new(),
+        };
+        let is_kind = |file_type: &FileType| match query.kind.as_deref() {
+            Some(kind) => file_type.kind.name() == kind,
+            None => true,
+        };
+        let mut search = |path: String, text: &str| {
+            if results.truncated || !is_kind(&classify(&path, text.as_bytes())) {
+                return;
+            }
+            let lines: Vec<&str> = text.lines().collect();
+            for (i, line) in lines.iter().enumerate() {
+                if !regex.is_match(line) {
+                    continue;
+                }
+                if results.matches.len() >= query.max_matches {
+                    results.truncated = true;
+                    return;
+                }
+                results.matches.push(GrepMatch {
+                    path: path.clone(),
+                    line_no: i + 1,
+                    line: line.to_string(),
+                    before: lines[i.saturating_sub(query.context)..i].iter().map(|l| l.to_string()).collect(),
+                    after: lines[i + 1..(i + 1 + query.context).min(lines.len())].iter().map(|l| l.to_string()).collect(),
+                });
+            }
+        };
+        match object.kind() {
+            Some(ObjectType::Tree) => {
+                let prefix = if path.is_empty() { "".to_string() } else { format!("{}/", path) };
+                let skipped = for_each_text_blob(repo, object.as_tree().unwrap(), MAX_SEARCH_BLOB_SIZE, |sub_path, text| {
+                    search(format!("{}{}", prefix, sub_path), text)
+                })?;
+                results.skipped = skipped.into_iter()
+                    .filter(|(_, file_type)| is_kind(file_type))
+                    .map(|(sub_path, _)| format!("{}{}", prefix, sub_path))
+                    .collect();
+            }
+            Some(ObjectType::Blob) => {
+                let blob = object.as_blob().unwrap();
+                if !blob.is_binary() {
+                    match std::str::from_utf8(blob.content()) {
+                        Ok(text) if blob.size() <= MAX_SEARCH_BLOB_SIZE => search(path.to_string(), text),
+                        _ if is_kind(&classify(path, blob.content())) => results.skipped.push(path.to_string()),
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+        Ok(results)
+    })).await
+}
+
+// Extract the RDF metadata from the CellML and RDF files at the commit
+// (default branch if not specified), replacing what was extracted before.
+pub async fn index_metadata(git_pmr_accessor: &GitPmrAccessor, commit_id: Option<&str>) -> Result<u64, PmrError> {
+    let git_root = git_pmr_accessor.git_root.clone();
+    let workspace_id = git_pmr_accessor.workspace.id;
+    let commit_id = commit_id.map(String::from);
+    let (commit_id, triples) = spawn_git(move || with_repo(&git_root, workspace_id, |repo| {
+        let commit = find_commit(repo, commit_id.as_deref())?;
+        let mut triples = Vec::new();
+        for_each_text_blob(repo, &commit.tree()?, MAX_METADATA_BLOB_SIZE, |path, text| {
+            if !(path.ends_with(".cellml") || path.ends_with(".rdf")) {
+                return;
+            }
+            match extract_triples(text) {
+                Ok(found) => triples.extend(found.into_iter().map(|triple| (path.clone(), triple))),
+                Err(e) => warn!("failed to extract metadata from {}: {}", path, e),
+            }
+        })?;
+        Ok((format!("{}", commit.id()), triples))
+    })).await?;
+
+    info!("Indexing {} metadata triples at commit {}", triples.len(), commit_id);
+    WorkspaceMetadataBackend::index_workspace_metadata(
+        &git_pmr_accessor.backend, &git_pmr_accessor.caller, workspace_id, &commit_id, &triples).await
+}
+
+// Fill in the keywords of the workspace from the subjects given in the
+// metadata extracted at the commit (default branch if not specified),
+// replacing those extracted before. The metadata is extracted first if
+// there is none for the commit, as it may never have been.
+pub async fn index_keywords(git_pmr_accessor: &GitPmrAccessor, commit_id: Option<&str>) -> Result<u64, PmrError> {
+    let backend = &git_pmr_accessor.backend;
+    let caller = &git_pmr_accessor.caller;
+    let workspace_id = git_pmr_accessor.workspace.id;
+    let commit_id = resolve_commit_id(git_pmr_accessor, commit_id).await?;
+    let mut recs = WorkspaceMetadataBackend::get_workspace_metadata(backend, caller, workspace_id, &commit_id).await?;
+    if recs.is_empty() {
+        index_metadata(git_pmr_accessor, Some(&commit_id)).await?;
+        recs = WorkspaceMetadataBackend::get_workspace_metadata(backend, caller, workspace_id, &commit_id).await?;
+    }
+    // blank node labels are only unique within the file they came from
+    let mut files: HashMap<String, Vec<Triple>> = HashMap::new();
+    for rec in recs {
+        files.entry(rec.path).or_default().push(Triple {
+            subject: rec.subject,
+            predicate: rec.predicate,
+            object: rec.object,
+            literal: rec.literal,
+        });
+    }
+    let keywords: Vec<String> = files.values().flat_map(|triples| extract_keywords(triples)).collect();
+    info!("Indexing {} keywords at commit {}", keywords.len(), commit_id);
+    WorkspaceKeywordBackend::index_workspace_keywords(
+        &git_pmr_accessor.backend, &git_pmr_accessor.caller, git_pmr_accessor.workspace.id, &keywords).await
+}
+
+// Record the imports made by the CellML models at the commit (default
+// branch if not specified), replacing what was recorded before.
+pub async fn index_imports(git_pmr_accessor: &GitPmrAccessor, commit_id: Option<&str>) -> Result<u64, PmrError> {
+    let (commit_id, imports) = process_pathinfo(
+        git_pmr_accessor, commit_id, None, collect_imports).await??;
+    info!("Indexing {} imports at commit {}", imports.len(), commit_id);
+    WorkspaceImportBackend::index_workspace_imports(
+        &git_pmr_accessor.backend, &git_pmr_accessor.caller, git_pmr_accessor.workspace.id, &commit_id, &imports).await
+}
+
+fn collect_imports(git_result_set: &GitResultSet) -> Result<(String, Vec<WorkspaceImport>), PmrError> {
+    let repo = git_result_set.repo;
+    let commit_id = format!("{}", git_result_set.commit.id());
+    let tree = git_result_set.commit.tree()?;
+    let submodules = read_gitmodules(repo, &tree);
+    let mut imports = Vec::new();
+    for_each_text_blob(repo, &tree, MAX_METADATA_BLOB_SIZE, |path, text| {
+        if !path.ends_with(".cellml") {
+            return;
+        }
+        match extract_imports(text) {
+            Ok(hrefs) => imports.extend(hrefs.into_iter().map(
+                |href| resolve_import(&tree, &commit_id, &submodules, &path, href))),
+            Err(e) => warn!("failed to extract imports from {}: {}", path, e),
+        }
     })?;
+    Ok((commit_id, imports))
+}
 
-    tags.iter().map(|(name, oid)| async move {
-        match WorkspaceTagBackend::index_workspace_tag(backend, workspace.id, &name, &oid).await {
-            Ok(_) => info!("indexed tag: {}", name),
-            Err(e) => warn!("tagging error: {:?}", e),
+fn resolve_import(
+    tree: &Tree,
+    commit_id: &str,
+    submodules: &[(String, String)],
+    path: &str,
+    href: String,
+) -> WorkspaceImport {
+    let target = match resolve_href(path, &href) {
+        Some(target) => target,
+        None => return WorkspaceImport {
+            path: path.to_string(),
+            target_url: if href.contains("://") { Some(href.clone()) } else { None },
+            href: href,
+            target_commit_id: None,
+            target_path: None,
+        },
+    };
+    // a target inside a submodule is in the workspace the submodule is from
+    for (sub_path, url) in submodules {
+        if let Some(rest) = target.strip_prefix(&format!("{}/", sub_path)) {
+            let gitlink = tree.get_path(Path::new(sub_path)).ok()
+                .filter(|entry| entry.kind() == Some(ObjectType::Commit));
+            return WorkspaceImport {
+                path: path.to_string(),
+                href: href,
+                target_url: Some(url.clone()),
+                target_commit_id: gitlink.map(|entry| format!("{}", entry.id())),
+                target_path: Some(rest.to_string()),
+            };
         }
-    }).collect::<FuturesUnordered<_>>().collect::<Vec<_>>().await;
+    }
+    WorkspaceImport {
+        path: path.to_string(),
+        href: href,
+        target_url: None,
+        target_commit_id: Some(commit_id.to_string()),
+        target_path: Some(target),
+    }
+}
 
-    Ok(())
+// The (path, url) of every submodule listed in .gitmodules in the tree.
+fn read_gitmodules(repo: &Repository, tree: &Tree) -> Vec<(String, String)> {
+    let blob = match tree.get_path(Path::new(".gitmodules"))
+        .and_then(|entry| repo.find_blob(entry.id()))
+    {
+        Ok(blob) => blob,
+        Err(_) => return Vec::new(),
+    };
+    let mut submodules = Vec::new();
+    let mut path = None;
+    let mut url = None;
+    for line in String::from_utf8_lossy(blob.content()).lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            if let (Some(p), Some(u)) = (path.take(), url.take()) {
+                submodules.push((p, u));
+            }
+            continue;
+        }
+        let mut kv = line.splitn(2, '=').map(str::trim);
+        match (kv.next(), kv.next()) {
+            (Some("path"), Some(v)) => path = Some(v.trim_end_matches('/').to_string()),
+            (Some("url"), Some(v)) => url = Some(v.to_string()),
+            _ => (),
+        }
+    }
+    if let (Some(p), Some(u)) = (path, url) {
+        submodules.push((p, u));
+    }
+    submodules
 }
 
-pub async fn get_obj_by_spec(git_pmr_accessor: &GitPmrAccessor, spec: &str) -> anyhow::Result<()> {
-    let git_root = &git_pmr_accessor.git_root;
-    let workspace = &git_pmr_accessor.workspace;
-    let repo_dir = git_root.join(workspace.id.to_string());
-    let repo = Repository::open_bare(repo_dir)?;
-    let obj = repo.revparse_single(spec)?;
-    info!("Found object {} {}", obj.kind().unwrap().str(), obj.id());
-    info!("{:?}", object_to_info(&repo, &obj));
-    Ok(())
+// Resolve the spec (default branch if not specified) to a commit id.
+pub async fn resolve_commit_id(git_pmr_accessor: &GitPmrAccessor, commit_id: Option<&str>) -> Result<String, PmrError> {
+    check_visible(git_pmr_accessor).await?;
+    let git_root = git_pmr_accessor.git_root.clone();
+    let workspace_id = git_pmr_accessor.workspace.id;
+    let commit_id = commit_id.map(String::from);
+    spawn_git(move || with_repo(&git_root, workspace_id, |repo| {
+        Ok(format!("{}", find_commit(repo, commit_id.as_deref())?.id()))
+    })).await
+}
+
+fn find_commit<'repo>(repo: &'repo Repository, commit_id: Option<&str>) -> Result<Commit<'repo>, PmrError> {
+    // TODO the default value should be the default (main?) branch.
+    // TODO the sync procedure should fast forward of sort
+    // TODO the model should have a field for main branch
+    let spec = commit_id.unwrap_or("origin/HEAD");
+    let obj = repo.revparse_single(spec)
+        .map_err(|_| PmrError::InvalidRevision(spec.to_string()))?;
+    match obj.into_commit() {
+        Ok(commit) => {
+            info!("Found commit {}", commit.id());
+            Ok(commit)
+        }
+        Err(_) => Err(PmrError::InvalidRevision(spec.to_string())),
+    }
+}
+
+// Call f with the full path and the content of every blob below the tree
+// that is text and no larger than max_size. The blobs that are not binary
+// but were passed over, being larger or not UTF-8, are returned along with
+// what they were taken to be.
+fn for_each_text_blob<F>(
+    repo: &Repository, tree: &Tree, max_size: usize, mut f: F
+) -> Result<Vec<(String, FileType)>, git2::Error>
+where
+    F: FnMut(String, &str),
+{
+    let mut skipped = Vec::new();
+    tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+        if entry.kind() != Some(ObjectType::Blob) {
+            return TreeWalkResult::Ok;
+        }
+        let blob = match repo.find_blob(entry.id()) {
+            Ok(blob) => blob,
+            Err(e) => {
+                warn!("failed to read blob {}: {}", entry.id(), e);
+                return TreeWalkResult::Ok;
+            }
+        };
+        let name = match entry.name() {
+            Some(name) if !blob.is_binary() => name,
+            _ => return TreeWalkResult::Ok,
+        };
+        let path = format!("{}{}", root, name);
+        match std::str::from_utf8(blob.content()) {
+            Ok(text) if blob.size() <= max_size => f(path, text),
+            _ => {
+                let file_type = classify(&path, blob.content());
+                skipped.push((path, file_type));
+            }
+        }
+        TreeWalkResult::Ok
+    })?;
+    Ok(skipped)
 }
 
 pub fn stream_blob(mut writer: impl Write, blob: &Blob) -> std::result::Result<usize, std::io::Error> {
-    writer.write(blob.content())
+    writer.write_all(blob.content())?;
+    Ok(blob.size())
+}
+
+// A blob is identified by its content, so its id is all an ETag needs.
+pub fn blob_etag(oid: git2::Oid) -> String {
+    format!("\"{}\"", oid)
+}
+
+#[derive(Debug, Clone)]
+pub struct BlobStat {
+    pub id: String,
+    pub size: u64,
+    pub etag: String,
+}
+
+// The bytes from start to end inclusive, as in an HTTP Content-Range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    // Resolve the value of an HTTP Range header against the size of the
+    // blob. Only a single range is supported; a suffix range (bytes=-n) is
+    // the last n bytes, and an end past the end of the blob is clamped.
+    pub fn parse(header: &str, size: u64) -> Result<ByteRange, PmrError> {
+        let spec = header.trim().strip_prefix("bytes=")
+            .filter(|spec| !spec.contains(','))
+            .ok_or_else(|| PmrError::InvalidInput(format!("unsupported range '{}'", header)))?;
+        let (start, end) = spec.split_once('-')
+            .ok_or_else(|| PmrError::InvalidInput(format!("invalid range '{}'", header)))?;
+        let parse = |value: &str| value.trim().parse::<u64>()
+            .map_err(|_| PmrError::InvalidInput(format!("invalid range '{}'", header)));
+        let range = match (start.trim(), end.trim()) {
+            ("", "") => return Err(PmrError::InvalidInput(format!("invalid range '{}'", header))),
+            ("", suffix) => {
+                let suffix = parse(suffix)?;
+                if suffix == 0 || size == 0 {
+                    return Err(PmrError::RangeNotSatisfiable(size));
+                }
+                ByteRange { start: size.saturating_sub(suffix), end: size - 1 }
+            }
+            (start, "") => ByteRange { start: parse(start)?, end: size.saturating_sub(1) },
+            (start, end) => ByteRange { start: parse(start)?, end: parse(end)?.min(size.saturating_sub(1)) },
+        };
+        range.check(size)?;
+        Ok(range)
+    }
+
+    pub fn len(&self) -> u64 {
+        (self.end + 1).saturating_sub(self.start)
+    }
+
+    // Never true of a range from parse, which would not be satisfiable.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn check(&self, size: u64) -> Result<(), PmrError> {
+        if self.start > self.end || self.end >= size {
+            return Err(PmrError::RangeNotSatisfiable(size));
+        }
+        Ok(())
+    }
+}
+
+// The id, size and ETag of the blob at the path at the commit (default
+// branch if not specified), without reading its content, e.g. to answer
+// a conditional request or resolve a range before streaming the blob.
+pub async fn stat_blob(
+    git_pmr_accessor: &GitPmrAccessor, commit_id: Option<&str>, path: &str
+) -> Result<BlobStat, PmrError> {
+    check_visible(git_pmr_accessor).await?;
+    let git_root = git_pmr_accessor.git_root.clone();
+    let workspace_id = git_pmr_accessor.workspace.id;
+    let commit_id = commit_id.map(String::from);
+    let path = path.trim_matches('/').to_string();
+    spawn_git(move || with_repo(&git_root, workspace_id, |repo| {
+        let commit = find_commit(repo, commit_id.as_deref())?;
+        let entry = commit.tree()?.get_path(Path::new(&path))
+            .map_err(|_| PmrError::PathNotFound(path.clone()))?;
+        if entry.kind() != Some(ObjectType::Blob) {
+            return Err(PmrError::NotABlob(path.clone()));
+        }
+        let (size, _) = repo.odb()?.read_header(entry.id())?;
+        Ok(BlobStat {
+            id: format!("{}", entry.id()),
+            size: size as u64,
+            etag: blob_etag(entry.id()),
+        })
+    })).await
+}
+
+// Write the blob (or only the range of it) with the id given by stat_blob
+// to the writer, returning the number of bytes written. The content is
+// streamed rather than read into memory whether or not the blob has been
+// packed; only packed blobs need the git executable, as for maintenance.
+pub async fn stream_blob_range<W: Write + Send + 'static>(
+    git_pmr_accessor: &GitPmrAccessor, blob_id: &str, range: Option<ByteRange>, writer: W
+) -> Result<u64, PmrError> {
+    check_visible(git_pmr_accessor).await?;
+    let git_root = git_pmr_accessor.git_root.clone();
+    let workspace_id = git_pmr_accessor.workspace.id;
+    let repo_dir = git_root.join(workspace_id.to_string());
+    let oid = git2::Oid::from_str(blob_id)
+        .map_err(|_| PmrError::NotFound(format!("blob {}", blob_id)))?;
+    let range = match spawn_git(move || with_repo(&git_root, workspace_id, |repo| {
+        resolve_blob_range(repo, oid, range)
+    })).await? {
+        Some(range) => range,
+        None => return Ok(0),
+    };
+    // the copy goes at the pace of whoever reads from the writer, so it
+    // holds up neither the cached repo nor one of the git threads
+    task::spawn_blocking(move || write_blob_range(&repo_dir, oid, range, writer)).await
+}
+
+// The range of the blob to write, all of it if not specified; None for an
+// empty blob, which has no range to speak of.
+fn resolve_blob_range(
+    repo: &Repository, oid: git2::Oid, range: Option<ByteRange>
+) -> Result<Option<ByteRange>, PmrError> {
+    let (size, kind) = repo.odb()?.read_header(oid)
+        .map_err(|_| PmrError::NotFound(format!("blob {}", oid)))?;
+    if kind != ObjectType::Blob {
+        return Err(PmrError::NotABlob(format!("{}", oid)));
+    }
+    match range {
+        Some(range) => {
+            range.check(size as u64)?;
+            Ok(Some(range))
+        }
+        None if size == 0 => Ok(None),
+        None => Ok(Some(ByteRange { start: 0, end: size as u64 - 1 })),
+    }
+}
+
+fn write_blob_range(
+    repo_dir: &Path, oid: git2::Oid, range: ByteRange, mut writer: impl Write
+) -> Result<u64, PmrError> {
+    let repo = Repository::open_bare(repo_dir)?;
+    let odb = repo.odb()?;
+    let written = match odb.reader(oid) {
+        Ok((reader, _, _)) => copy_range(reader, range, &mut writer)?,
+        // libgit2 can only stream loose objects, so anything that has been
+        // packed is streamed by git itself
+        Err(_) => {
+            let mut child = std::process::Command::new("git")
+                .arg("--git-dir")
+                .arg(repo.path())
+                .args(["cat-file", "blob", &oid.to_string()])
+                .stdout(std::process::Stdio::piped())
+                .stderr(std::process::Stdio::null())
+                .spawn()?;
+            let written = copy_range(child.stdout.take().unwrap(), range, &mut writer);
+            // with its output closed git stops early when only the start of
+            // the blob was wanted, so how it exits says nothing
+            child.kill().ok();
+            child.wait()?;
+            written?
+        }
+    };
+    if written != range.len() {
+        return Err(PmrError::Io(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof, format!("blob {} ended before the range did", oid))));
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+fn copy_range(mut reader: impl Read, range: ByteRange, writer: &mut impl Write) -> std::io::Result<u64> {
+    std::io::copy(&mut (&mut reader).take(range.start), &mut std::io::sink())?;
+    std::io::copy(&mut reader.take(range.len()), writer)
 }
 
 // commit_id/path should be a pathinfo struct?
-pub async fn process_pathinfo<T>(
+pub async fn process_pathinfo<T: Send + 'static>(
     git_pmr_accessor: &GitPmrAccessor,
     commit_id: Option<&str>,
     path: Option<&str>,
     processor: fn(&GitResultSet) -> T
-) -> anyhow::Result<T> {
-    let git_root = &git_pmr_accessor.git_root;
-    let workspace = &git_pmr_accessor.workspace;
-    let repo_dir = git_root.join(workspace.id.to_string());
-    let repo = Repository::open_bare(repo_dir)?;
-    // TODO the default value should be the default (main?) branch.
-    // TODO the sync procedure should fast forward of sort
-    // TODO the model should have a field for main branch
-    let obj = repo.revparse_single(commit_id.unwrap_or("origin/HEAD"))?;
-    // TODO streamline this a bit.
-    match obj.kind() {
-        Some(ObjectType::Commit) => {
-            info!("Found {} {}", obj.kind().unwrap().str(), obj.id());
-        }
-        Some(_) | None => bail!("'{}' does not refer to a valid commit", commit_id.unwrap_or(""))
-    }
-    let commit = obj.as_commit().unwrap();
-    let tree = commit.tree()?;
-    info!("Found tree {}", tree.id());
-    // TODO only further navigate into tree_entry if path
-    let git_object = match path {
-        Some(s) => {
-            let tree_entry = tree.get_path(Path::new(s))?;
-            info!("Found tree_entry {} {}", tree_entry.kind().unwrap().str(), tree_entry.id());
-            tree_entry.to_object(&repo)?
-        },
-        None => {
-            info!("No path provided; using root tree entry");
-            tree.into_object()
-        }
+) -> Result<T, PmrError> {
+    check_visible(git_pmr_accessor).await?;
+    let git_root = git_pmr_accessor.git_root.clone();
+    let workspace_id = git_pmr_accessor.workspace.id;
+    let commit_id = commit_id.map(String::from);
+    let path = path.map(String::from);
+    spawn_git(move || with_repo(&git_root, workspace_id, |repo| {
+        let path = path.as_deref();
+        let commit = find_commit(repo, commit_id.as_deref())?;
+        let tree = commit.tree()?;
+        info!("Found tree {}", tree.id());
+        // TODO only further navigate into tree_entry if path
+        let git_object = match path {
+            Some(s) => {
+                let tree_entry = tree.get_path(Path::new(s))
+                    .map_err(|_| PmrError::PathNotFound(s.to_string()))?;
+                info!("Found tree_entry {} {}", tree_entry.kind().unwrap().str(), tree_entry.id());
+                tree_entry.to_object(repo)?
+            },
+            None => {
+                info!("No path provided; using root tree entry");
+                tree.into_object()
+            }
+        };
+        info!("using git_object {} {}", git_object.kind().unwrap().str(), git_object.id());
+        let git_result_set = GitResultSet {
+            repo: repo,
+            commit: &commit,
+            path: path.unwrap_or(""),
+            object: git_object,
+        };
+        Ok(processor(&git_result_set))
+    })).await
+}
+
+// List every entry below the path (the root if not specified) at the
+// commit (default branch if not specified), along with the commit that
+// last changed each of them.
+pub async fn list_tree(
+    git_pmr_accessor: &GitPmrAccessor, commit_id: Option<&str>, path: Option<&str>
+) -> Result<Arc<Vec<TreeListingEntry>>, PmrError> {
+    check_visible(git_pmr_accessor).await?;
+    let workspace_id = git_pmr_accessor.workspace.id;
+    let commit_id = resolve_commit_id(git_pmr_accessor, commit_id).await?;
+    let path = path.map(|path| path.trim_matches('/')).filter(|path| !path.is_empty());
+    if let Some(listing) = cached_tree_listing(workspace_id, &commit_id, path.unwrap_or("")) {
+        return Ok(listing);
+    }
+    let mut entries = process_pathinfo(
+        git_pmr_accessor, Some(&commit_id), path, collect_tree_listing).await??;
+
+    // the walk for the last commits may cover the whole history, so it is
+    // done with a handle of its own rather than holding up the cached one
+    let repo_dir = git_pmr_accessor.git_root.join(workspace_id.to_string());
+    let dir = path.unwrap_or("").to_string();
+    let paths = entries.iter().map(|entry| entry.path.clone()).collect::<Vec<_>>();
+    let commit_id_ = commit_id.clone();
+    let mut last_commits = spawn_git(move || {
+        let repo = Repository::open_bare(&repo_dir)?;
+        let commit = repo.find_commit(git2::Oid::from_str(&commit_id_)?)?;
+        find_last_commits(&repo, &commit, &dir, paths.iter().map(String::as_str))
+    }).await?;
+    for entry in entries.iter_mut() {
+        entry.last_commit = last_commits.remove(&entry.path);
+    }
+
+    let listing = Arc::new(entries);
+    cache_tree_listing(workspace_id, &commit_id, path.unwrap_or(""), listing.clone());
+    Ok(listing)
+}
+
+fn collect_tree_listing(git_result_set: &GitResultSet) -> Result<Vec<TreeListingEntry>, PmrError> {
+    let repo = git_result_set.repo;
+    let tree = match git_result_set.object.as_tree() {
+        Some(tree) => tree,
+        None => return Err(PmrError::NotATree(git_result_set.path.to_string())),
     };
-    info!("using git_object {} {}", git_object.kind().unwrap().str(), git_object.id());
-    let git_result_set = GitResultSet {
-        repo: &repo,
-        commit: commit,
-        path: path.unwrap_or(""),
-        object: git_object,
+    let prefix = match git_result_set.path {
+        "" => "".to_string(),
+        path => format!("{}/", path),
     };
-    Ok(processor(&git_result_set))
+    let odb = repo.odb()?;
+    let mut entries = Vec::new();
+    tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+        let size = match entry.kind() {
+            Some(ObjectType::Blob) => odb.read_header(entry.id()).ok().map(|(size, _)| size as u64),
+            _ => None,
+        };
+        entries.push(TreeListingEntry {
+            path: format!("{}{}{}", prefix, root, entry.name().unwrap_or("")),
+            filemode: format!("{:06o}", entry.filemode()),
+            kind: entry.kind().map_or("unknown", |kind| kind.str()).to_string(),
+            id: format!("{}", entry.id()),
+            size: size,
+            last_commit: None,
+        });
+        TreeWalkResult::Ok
+    })?;
+    Ok(entries)
 }
 
-fn blob_to_info(blob: &Blob) -> ObjectInfo {
+fn entry_id(tree: &Tree, path: &str) -> Option<git2::Oid> {
+    match path {
+        "" => Some(tree.id()),
+        path => tree.get_path(Path::new(path)).ok().map(|entry| entry.id()),
+    }
+}
+
+// Walk back from the commit to find the commit that last changed each of
+// the paths below dir. As with git log, a path is followed into the first
+// parent it is unchanged in, and the commit where no such parent exists
+// (or that has no parents at all) is the one that changed it; the paths
+// that meet at a commit are looked at together, newest commit first.
+fn find_last_commits<'a>(
+    repo: &Repository, commit: &Commit, dir: &str, paths: impl Iterator<Item = &'a str>
+) -> Result<HashMap<String, LastCommit>, PmrError> {
+    let mut found = HashMap::new();
+    let mut pending: HashMap<git2::Oid, Vec<&str>> = HashMap::new();
+    let mut queue = std::collections::BinaryHeap::new();
+    pending.insert(commit.id(), paths.collect());
+    queue.push((commit.time().seconds(), commit.id()));
+    while let Some((_, oid)) = queue.pop() {
+        let paths = match pending.remove(&oid) {
+            Some(paths) => paths,
+            None => continue,
+        };
+        let commit = repo.find_commit(oid)?;
+        let tree = commit.tree()?;
+        let parents = commit.parents().collect::<Vec<_>>();
+        let parent_trees = parents.iter().map(|parent| parent.tree()).collect::<Result<Vec<_>, _>>()?;
+        let mut follow = |parent: &Commit, paths: Vec<&'a str>| {
+            if !pending.contains_key(&parent.id()) {
+                queue.push((parent.time().seconds(), parent.id()));
+            }
+            pending.entry(parent.id()).or_default().extend(paths);
+        };
+        // nothing below dir changed unless dir itself did
+        let dir_id = entry_id(&tree, dir);
+        if let Some(i) = parent_trees.iter().position(|parent| entry_id(parent, dir) == dir_id) {
+            follow(&parents[i], paths);
+            continue;
+        }
+        for path in paths {
+            let id = entry_id(&tree, path);
+            match parent_trees.iter().position(|parent| entry_id(parent, path) == id) {
+                Some(i) => follow(&parents[i], vec![path]),
+                None => {
+                    found.insert(path.to_string(), LastCommit {
+                        commit_id: format!("{}", commit.id()),
+                        time: commit.time().seconds(),
+                        summary: commit.summary().unwrap_or("").to_string(),
+                    });
+                }
+            }
+        }
+    }
+    Ok(found)
+}
+
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    pub commit_id: String,
+    pub author: String,
+    pub time: i64,
+    pub summary: String,
+    // the path as of the commit, which differs from the one asked for if
+    // the file was since renamed
+    pub path: String,
+}
+
+impl std::fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} - {} - {} - {} - {}",
+            &self.commit_id,
+            // the time is whatever the remote recorded, so may be out of range
+            match chrono::Utc.timestamp_opt(self.time, 0).single() {
+                Some(time) => time.to_rfc3339(),
+                None => self.time.to_string(),
+            },
+            &self.author,
+            &self.path,
+            &self.summary,
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlameLine {
+    pub line_no: usize,
+    pub commit_id: String,
+    pub author: String,
+    pub time: i64,
+    // the path the line came from, if not where it is now
+    pub orig_path: Option<String>,
+    pub content: String,
+}
+
+impl std::fmt::Display for BlameLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} - {} - {} - {}",
+            &self.commit_id[..7],
+            &self.author,
+            self.line_no,
+            &self.content,
+        )
+    }
+}
+
+// The commits that changed the path (the whole tree if not specified), up
+// to and including the commit (default branch if not specified), newest
+// first, following the path back through any renames.
+pub async fn file_history(
+    git_pmr_accessor: &GitPmrAccessor, commit_id: Option<&str>, path: Option<&str>
+) -> Result<Vec<HistoryEntry>, PmrError> {
+    process_pathinfo(git_pmr_accessor, commit_id, path, collect_history).await?
+}
+
+// Only the first parent of merges is followed, as with git log
+// --first-parent, so a change is reported against the merge bringing it
+// into the branch rather than the commit on the side branch. The walk
+// goes on past the commit that added the path, as the path may have been
+// deleted and so have history from before that.
+fn collect_history(git_result_set: &GitResultSet) -> Result<Vec<HistoryEntry>, PmrError> {
+    let repo = git_result_set.repo;
+    let mut path = git_result_set.path.trim_matches('/').to_string();
+    let mut history = Vec::new();
+    let mut revwalk = repo.revwalk()?;
+    revwalk.simplify_first_parent()?;
+    revwalk.push(git_result_set.commit.id())?;
+    for oid in revwalk {
+        let commit = repo.find_commit(oid?)?;
+        let tree = commit.tree()?;
+        let parent_tree = match commit.parents().next() {
+            Some(parent) => Some(parent.tree()?),
+            None => None,
+        };
+        let id = entry_id(&tree, &path);
+        let parent_id = parent_tree.as_ref().and_then(|parent_tree| entry_id(parent_tree, &path));
+        if id == parent_id {
+            continue;
+        }
+        history.push(HistoryEntry {
+            commit_id: format!("{}", commit.id()),
+            author: format!("{}", commit.author()),
+            time: commit.time().seconds(),
+            summary: commit.summary().unwrap_or("").to_string(),
+            path: path.clone(),
+        });
+        // added here, unless it was renamed from something else
+        if let (Some(parent_tree), None) = (parent_tree, parent_id) {
+            if let Some(old_path) = find_rename(repo, &parent_tree, &tree, &path)? {
+                path = old_path;
+            }
+        }
+    }
+    Ok(history)
+}
+
+// The path the file at path in new was renamed from, relative to old.
+fn find_rename(repo: &Repository, old: &Tree, new: &Tree, path: &str) -> Result<Option<String>, PmrError> {
+    let mut diff = repo.diff_tree_to_tree(Some(old), Some(new), None)?;
+    diff.find_similar(Some(git2::DiffFindOptions::new().renames(true)))?;
+    let old_path = diff.deltas()
+        .filter(|delta| delta.status() == git2::Delta::Renamed)
+        .find(|delta| delta.new_file().path() == Some(Path::new(path)))
+        .and_then(|delta| delta.old_file().path().map(|path| path.to_string_lossy().into_owned()));
+    Ok(old_path)
+}
+
+// The commit that last changed each line of the file at the path, as of
+// the commit (default branch if not specified).
+pub async fn blame(
+    git_pmr_accessor: &GitPmrAccessor, commit_id: Option<&str>, path: &str
+) -> Result<Vec<BlameLine>, PmrError> {
+    process_pathinfo(git_pmr_accessor, commit_id, Some(path), collect_blame).await?
+}
+
+fn collect_blame(git_result_set: &GitResultSet) -> Result<Vec<BlameLine>, PmrError> {
+    let repo = git_result_set.repo;
+    let path = git_result_set.path.trim_matches('/');
+    let blob = match git_result_set.object.as_blob() {
+        Some(blob) => blob,
+        None => return Err(PmrError::NotABlob(path.to_string())),
+    };
+    let mut opts = git2::BlameOptions::new();
+    opts.newest_commit(git_result_set.commit.id());
+    let blame = repo.blame_file(Path::new(path), Some(&mut opts))?;
+    let content = String::from_utf8_lossy(blob.content());
+    let mut lines = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        let line_no = i + 1;
+        let hunk = match blame.get_line(line_no) {
+            Some(hunk) => hunk,
+            None => continue,
+        };
+        let orig_path = hunk.path()
+            .map(|orig_path| orig_path.to_string_lossy().into_owned())
+            .filter(|orig_path| orig_path != path);
+        let signature = hunk.final_signature();
+        lines.push(BlameLine {
+            line_no: line_no,
+            commit_id: format!("{}", hunk.final_commit_id()),
+            author: format!("{}", signature),
+            time: signature.when().seconds(),
+            orig_path: orig_path,
+            content: line.to_string(),
+        });
+    }
+    Ok(lines)
+}
+
+fn blob_to_info(blob: &Blob, path: &str) -> ObjectInfo {
     ObjectInfo::FileInfo {
         size: blob.size() as u64,
         binary: blob.is_binary(),
+        file_type: classify(path, blob.content()),
     }
 }
 
@@ -223,12 +1358,14 @@ fn commit_to_info(commit: &Commit) -> ObjectInfo {
     }
 }
 
-pub fn object_to_info(repo: &Repository, git_object: &Object) -> Option<ObjectInfo> {
+// The path is that of the object in its tree, if known, as the type of a
+// file is partly told by its name.
+pub fn object_to_info(repo: &Repository, git_object: &Object, path: &str) -> Option<ObjectInfo> {
     // TODO split off to a formatter version?
     // alternatively, produce some structured data?
     match git_object.kind() {
         Some(ObjectType::Blob) => {
-            Some(blob_to_info(git_object.as_blob().unwrap()))
+            Some(blob_to_info(git_object.as_blob().unwrap(), path))
         }
         Some(ObjectType::Tree) => {
             Some(tree_to_info(&repo, git_object.as_tree().unwrap()))
@@ -259,11 +1396,11 @@ pub fn stream_git_result_set(mut writer: impl Write, git_result_set: &GitResultS
         &git_result_set.commit.id(),
         commit_to_info(&git_result_set.commit),
         git_result_set.path,
-        object_to_info(&git_result_set.repo, &git_result_set.object),
+        object_to_info(&git_result_set.repo, &git_result_set.object, git_result_set.path),
     ).as_bytes()).unwrap();
 }
 
-pub fn stream_git_result_set_blob(writer: impl Write, git_result_set: &GitResultSet) -> anyhow::Result<()> {
+pub fn stream_git_result_set_blob(writer: impl Write, git_result_set: &GitResultSet) -> Result<(), PmrError> {
     match git_result_set.object.kind() {
         Some(ObjectType::Blob) => {
             match git_result_set.object.as_blob() {
@@ -271,11 +1408,199 @@ pub fn stream_git_result_set_blob(writer: impl Write, git_result_set: &GitResult
                     stream_blob(writer, blob)?;
                     Ok(())
                 }
-                None => bail!("failed to get blob from object")
+                None => Err(PmrError::NotABlob(git_result_set.path.to_string())),
             }
         }
         Some(_) | None => {
-            bail!("target is not a git blob")
+            Err(PmrError::NotABlob(git_result_set.path.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn commit_files(
+        repo: &Repository, time: i64, parents: &[git2::Oid], files: &[(&str, &str)]
+    ) -> git2::Oid {
+        let mut builder = repo.treebuilder(None).unwrap();
+        for (name, content) in files {
+            builder.insert(name, repo.blob(content.as_bytes()).unwrap(), 0o100644).unwrap();
+        }
+        let tree = repo.find_tree(builder.write().unwrap()).unwrap();
+        let sig = git2::Signature::new("test", "test@example.com", &git2::Time::new(time, 0)).unwrap();
+        let parents = parents.iter().map(|id| repo.find_commit(*id).unwrap()).collect::<Vec<_>>();
+        let parents = parents.iter().collect::<Vec<_>>();
+        repo.commit(None, &sig, &sig, &time.to_string(), &tree, &parents).unwrap()
+    }
+
+    #[async_std::test]
+    async fn test_probe_url() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path().join("repo")).unwrap();
+        let url = url::Url::from_file_path(dir.path().join("repo")).unwrap();
+        assert_eq!(probe_url(url.as_str()).await, Ok(()));
+        let commit = commit_files(&repo, 100, &[], &[("a", "1")]);
+        repo.reference("refs/heads/main", commit, false, "").unwrap();
+        assert_eq!(probe_url(url.as_str()).await, Ok(()));
+
+        let url = url::Url::from_file_path(dir.path().join("missing")).unwrap();
+        assert!(matches!(probe_url(url.as_str()).await, Err(WorkspaceUrlError::Unreachable(_))));
+    }
+
+    #[test]
+    fn test_find_last_commits() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path()).unwrap();
+        let root = commit_files(&repo, 100, &[], &[("a", "1"), ("b", "1"), ("c", "1")]);
+        let left = commit_files(&repo, 200, &[root], &[("a", "2"), ("b", "1"), ("c", "1")]);
+        // newer than left, but its change to a was not taken by the merge
+        let right = commit_files(&repo, 300, &[root], &[("a", "3"), ("b", "3"), ("c", "1")]);
+        let merge = commit_files(&repo, 400, &[left, right], &[("a", "2"), ("b", "3"), ("c", "1")]);
+
+        let found = find_last_commits(
+            &repo, &repo.find_commit(merge).unwrap(), "", vec!["a", "b", "c"].into_iter()).unwrap();
+        assert_eq!(found["a"].commit_id, left.to_string());
+        assert_eq!(found["b"].commit_id, right.to_string());
+        assert_eq!(found["c"].commit_id, root.to_string());
+    }
+
+    #[test]
+    fn test_collect_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path()).unwrap();
+        let added = commit_files(&repo, 100, &[], &[("a", "one\n"), ("x", "")]);
+        let deleted = commit_files(&repo, 200, &[added], &[("x", "")]);
+        let readded = commit_files(&repo, 300, &[deleted], &[("a", "two\n"), ("x", "")]);
+        let renamed = commit_files(&repo, 400, &[readded], &[("b", "two\n"), ("x", "")]);
+        let unrelated = commit_files(&repo, 500, &[renamed], &[("b", "two\n"), ("x", "1")]);
+        let changed = commit_files(&repo, 600, &[unrelated], &[("b", "three\n"), ("x", "1")]);
+
+        let commit = repo.find_commit(changed).unwrap();
+        let object = commit.tree().unwrap().get_path(Path::new("b")).unwrap().to_object(&repo).unwrap();
+        let history = collect_history(&GitResultSet {
+            repo: &repo,
+            commit: &commit,
+            path: "b",
+            object: object,
+        }).unwrap();
+        let history = history.iter()
+            .map(|entry| (entry.commit_id.clone(), entry.path.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(history, vec![
+            (changed.to_string(), "b"),
+            (renamed.to_string(), "b"),
+            (readded.to_string(), "a"),
+            (deleted.to_string(), "a"),
+            (added.to_string(), "a"),
+        ]);
+    }
+
+    #[test]
+    fn test_history_entry_display() {
+        let entry = HistoryEntry {
+            commit_id: "0".repeat(40),
+            author: "test".to_string(),
+            time: i64::MAX,
+            summary: "summary".to_string(),
+            path: "a".to_string(),
+        };
+        assert!(entry.to_string().contains(&i64::MAX.to_string()));
+    }
+
+    fn blob_range(repo: &Repository, oid: git2::Oid, range: Option<ByteRange>) -> Vec<u8> {
+        let mut buf = Vec::new();
+        if let Some(range) = resolve_blob_range(repo, oid, range).unwrap() {
+            let written = write_blob_range(repo.path(), oid, range, &mut buf).unwrap();
+            assert_eq!(written, buf.len() as u64);
         }
+        buf
+    }
+
+    #[async_std::test]
+    async fn test_delete_workspace_and_repo() {
+        let backend = crate::utils::test_backend().await;
+        let dir = tempfile::tempdir().unwrap();
+        let git_root = dir.path();
+        let lock_file = git_root.join(LOCK_DIR);
+        let mut ids = Vec::new();
+        for quarantine in [true, false].iter() {
+            ids.push((WorkspaceBackend::add_workspace(
+                &backend, &Caller::System, &format!("https://example.com/{}", quarantine), "", "").await.unwrap(),
+                *quarantine));
+        }
+        for (id, quarantine) in ids {
+            Repository::init_bare(git_root.join(id.to_string())).unwrap();
+            drop(lock_workspace(git_root, id).await.unwrap());
+            assert!(lock_file.join(id.to_string()).exists());
+
+            let (_, repo_dir) = delete_workspace_and_repo(
+                &backend, &Caller::System, git_root, id, quarantine, false).await.unwrap().unwrap();
+            assert_eq!(repo_dir, Some(git_root.join(id.to_string())));
+            assert!(!git_root.join(id.to_string()).exists());
+            assert!(!lock_file.join(id.to_string()).exists());
+            assert_eq!(git_root.join(QUARANTINE_DIR).read_dir().unwrap().count(), 1);
+        }
+    }
+
+    #[test]
+    fn test_write_blob_range() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init_bare(dir.path()).unwrap();
+        let content = (0..100000).map(|i| format!("{}\n", i)).collect::<String>();
+        let commit = commit_files(&repo, 100, &[], &[("a", &content), ("empty", "")]);
+        repo.reference("refs/heads/main", commit, false, "").unwrap();
+        let oid = repo.find_commit(commit).unwrap().tree().unwrap().get_name("a").unwrap().id();
+        let empty = repo.find_commit(commit).unwrap().tree().unwrap().get_name("empty").unwrap().id();
+
+        let check = |repo: &Repository| {
+            assert_eq!(blob_range(repo, oid, None), content.as_bytes());
+            let range = ByteRange::parse("bytes=1000-1999", content.len() as u64).unwrap();
+            assert_eq!(blob_range(repo, oid, Some(range)), &content.as_bytes()[1000..2000]);
+            let range = ByteRange::parse("bytes=-5", content.len() as u64).unwrap();
+            assert_eq!(blob_range(repo, oid, Some(range)), b"9999\n");
+            assert_eq!(blob_range(repo, empty, None), b"");
+        };
+
+        assert!(repo.odb().unwrap().reader(oid).is_ok());
+        check(&repo);
+
+        for args in &[&["repack", "-a", "-d", "-q"][..], &["prune-packed"][..]] {
+            let status = std::process::Command::new("git")
+                .arg("--git-dir").arg(dir.path()).args(*args).status().unwrap();
+            assert!(status.success());
+        }
+        let repo = Repository::open_bare(dir.path()).unwrap();
+        // as packed, only git itself can stream it
+        assert!(repo.odb().unwrap().reader(oid).is_err());
+        check(&repo);
+
+        let tree = repo.find_commit(commit).unwrap().tree_id();
+        assert!(matches!(resolve_blob_range(&repo, tree, None), Err(PmrError::NotABlob(_))));
+        let range = ByteRange { start: 0, end: content.len() as u64 };
+        assert!(matches!(resolve_blob_range(&repo, oid, Some(range)), Err(PmrError::RangeNotSatisfiable(_))));
+    }
+
+    #[test]
+    fn test_byte_range_parse() {
+        assert_eq!(ByteRange::parse("bytes=0-9", 100).unwrap(), ByteRange { start: 0, end: 9 });
+        assert_eq!(ByteRange::parse("bytes=90-", 100).unwrap(), ByteRange { start: 90, end: 99 });
+        assert_eq!(ByteRange::parse("bytes=-10", 100).unwrap(), ByteRange { start: 90, end: 99 });
+        assert_eq!(ByteRange::parse("bytes=-200", 100).unwrap(), ByteRange { start: 0, end: 99 });
+        assert_eq!(ByteRange::parse("bytes=50-200", 100).unwrap(), ByteRange { start: 50, end: 99 });
+        assert_eq!(ByteRange::parse("bytes=5-5", 100).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_byte_range_parse_invalid() {
+        assert!(matches!(ByteRange::parse("bytes=0-1,5-6", 100), Err(PmrError::InvalidInput(_))));
+        assert!(matches!(ByteRange::parse("items=0-1", 100), Err(PmrError::InvalidInput(_))));
+        assert!(matches!(ByteRange::parse("bytes=-", 100), Err(PmrError::InvalidInput(_))));
+        assert!(matches!(ByteRange::parse("bytes=a-1", 100), Err(PmrError::InvalidInput(_))));
+        assert!(matches!(ByteRange::parse("bytes=100-", 100), Err(PmrError::RangeNotSatisfiable(100))));
+        assert!(matches!(ByteRange::parse("bytes=9-5", 100), Err(PmrError::RangeNotSatisfiable(100))));
+        assert!(matches!(ByteRange::parse("bytes=-0", 100), Err(PmrError::RangeNotSatisfiable(100))));
+        assert!(matches!(ByteRange::parse("bytes=0-", 0), Err(PmrError::RangeNotSatisfiable(0))));
     }
 }
@@ -0,0 +1,117 @@
+use serde::Deserialize;
+use std::fmt;
+use std::path::Path;
+
+use crate::error::PmrError;
+use crate::model::backend::SqliteBackend;
+use crate::model::user::Caller;
+use crate::model::workspace::WorkspaceBackend;
+use crate::model::workspace_url::check_workspace_url;
+use crate::repo::git::{
+    GitPmrAccessor,
+    git_sync_workspace,
+    probe_url,
+};
+
+// One workspace to be registered, as listed in a manifest.
+#[derive(Debug, Deserialize)]
+pub struct ManifestEntry {
+    pub url: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub long_description: String,
+}
+
+#[derive(Debug)]
+pub enum RegisterOutcome {
+    Registered(i64),
+    Synced(i64),
+    // registered, but the initial sync failed
+    SyncFailed(i64, String),
+    // already registered, including earlier in the same manifest
+    Skipped(i64),
+    Invalid(String),
+    Failed(String),
+}
+
+#[derive(Debug)]
+pub struct RegisterReport {
+    pub url: String,
+    pub outcome: RegisterOutcome,
+}
+
+impl std::fmt::Display for RegisterReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.outcome {
+            RegisterOutcome::Registered(id) => write!(f, "{} - registered - {}", &self.url, id),
+            RegisterOutcome::Synced(id) => write!(f, "{} - registered and synced - {}", &self.url, id),
+            RegisterOutcome::SyncFailed(id, e) => write!(f, "{} - registered, sync failed - {} - {}", &self.url, id, e),
+            RegisterOutcome::Skipped(id) => write!(f, "{} - already registered - {}", &self.url, id),
+            RegisterOutcome::Invalid(e) => write!(f, "{} - invalid - {}", &self.url, e),
+            RegisterOutcome::Failed(e) => write!(f, "{} - failed - {}", &self.url, e),
+        }
+    }
+}
+
+// Read a manifest of workspaces, in the format given by its extension:
+// csv (with a header row), json or yaml, each a list of entries.
+pub fn read_manifest(path: &Path) -> Result<Vec<ManifestEntry>, PmrError> {
+    let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    let file = std::fs::File::open(path)?;
+    let entries = match ext {
+        "csv" => csv::Reader::from_reader(file)
+            .deserialize()
+            .collect::<Result<Vec<ManifestEntry>, _>>()
+            .map_err(|e| PmrError::InvalidDocument(e.to_string()))?,
+        "json" => serde_json::from_reader(file)?,
+        "yaml" | "yml" => serde_yaml::from_reader(file)
+            .map_err(|e| PmrError::InvalidDocument(e.to_string()))?,
+        _ => return Err(PmrError::InvalidInput(
+            format!("unknown manifest format '{}', expected csv, json or yaml", ext))),
+    };
+    Ok(entries)
+}
+
+// Register every valid entry that isn't already registered, optionally
+// probing that each url is reachable first and syncing each once it is
+// registered. Problems with one entry are reported against it and don't
+// stop the others.
+pub async fn bulk_register(
+    backend: &SqliteBackend, caller: &Caller, git_root: &Path, entries: &[ManifestEntry], probe: bool, sync: bool
+) -> Result<Vec<RegisterReport>, PmrError> {
+    let mut reports = Vec::new();
+    for entry in entries {
+        let url = entry.url.trim().to_string();
+        let outcome = if let Err(e) = check_workspace_url(caller, &url) {
+            RegisterOutcome::Invalid(e.to_string())
+        }
+        else if let Some(id) = WorkspaceBackend::get_workspace_id_by_url(backend, &url).await? {
+            RegisterOutcome::Skipped(id)
+        }
+        else if let Err(e) = if probe { probe_url(&url).await } else { Ok(()) } {
+            RegisterOutcome::Invalid(e.to_string())
+        }
+        else {
+            match WorkspaceBackend::add_workspace(
+                backend, caller, &url, &entry.description, &entry.long_description).await
+            {
+                Ok(id) if sync => match register_sync(backend, caller, git_root, id).await {
+                    Ok(_) => RegisterOutcome::Synced(id),
+                    Err(e) => RegisterOutcome::SyncFailed(id, e.to_string()),
+                },
+                Ok(id) => RegisterOutcome::Registered(id),
+                Err(e) => RegisterOutcome::Failed(e.to_string()),
+            }
+        };
+        reports.push(RegisterReport { url: url, outcome: outcome });
+    }
+    Ok(reports)
+}
+
+async fn register_sync(backend: &SqliteBackend, caller: &Caller, git_root: &Path, id: i64) -> Result<(), PmrError> {
+    let workspace = WorkspaceBackend::get_workspace_by_id(backend, caller, id).await?;
+    let git_pmr_accessor = GitPmrAccessor::new(
+        SqliteBackend::new((*backend.pool).clone()), git_root.to_path_buf(), workspace, caller.clone());
+    git_sync_workspace(&git_pmr_accessor).await
+}
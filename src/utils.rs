@@ -1,5 +1,25 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
-pub fn timestamp() -> anyhow::Result<u64> {
-    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+use crate::error::PmrError;
+#[cfg(test)]
+use crate::model::backend::SqliteBackend;
+
+pub fn timestamp() -> Result<u64, PmrError> {
+    // only a clock set before the epoch can fail this
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .map_err(|e| PmrError::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))
+}
+
+// A backend on a fresh database of its own with every migration applied.
+#[cfg(test)]
+pub(crate) async fn test_backend() -> SqliteBackend {
+    // every connection to :memory: opens a database of its own
+    let pool = sqlx::sqlite::SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .unwrap();
+    sqlx::migrate!().run(&pool).await.unwrap();
+    SqliteBackend::new(pool)
 }